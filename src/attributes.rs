@@ -0,0 +1,364 @@
+use std::fmt::Display;
+
+/// Attributes whose values are comma-separated lists, as defined by the GFF3 specification.
+///
+/// InterProScan does not escape commas in free text such as `signature_desc`, so every
+/// other attribute is kept as a single value.
+pub const MULTI_VALUED: [&str; 5] = ["Parent", "Alias", "Note", "Dbxref", "Ontology_term"];
+
+/// The attribute column (column 9) of a GFF3 line.
+///
+/// Values are percent-decoded and stripped of the double quotes InterProScan puts around
+/// cross-references (`Dbxref="InterPro:IPR001357"`). Attributes keep the order they had in
+/// the input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes(Vec<(String, Vec<String>)>);
+
+/// The `Target` attribute of a match: the region of the sequence it aligns to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub id: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Attributes {
+    /// Parse a `key=value;key=value` column. Entries that are not `key=value` pairs are
+    /// skipped.
+    pub fn parse(column: &str) -> Self {
+        let mut attributes = Self::default();
+
+        for attr in column.split(';') {
+            if attr.trim().is_empty() {
+                continue;
+            }
+
+            let (key, value) = match attr.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => (key.trim(), value),
+                _ => {
+                    debug!("skipped attribute {:?}", attr);
+                    continue;
+                }
+            };
+
+            let values: Vec<String> = if MULTI_VALUED.contains(&key) {
+                split_unquoted(value, ',')
+                    .into_iter()
+                    .map(|x| percent_decode(unquote(x)))
+                    .collect()
+            } else {
+                vec![percent_decode(unquote(value))]
+            };
+
+            attributes.extend(percent_decode(key), values);
+        }
+
+        attributes
+    }
+
+    /// Append `values` to `key`, adding it after the existing attributes if needed.
+    pub fn extend<S: ToString>(&mut self, key: S, values: Vec<String>) {
+        let key = key.to_string();
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => existing.extend(values),
+            None => self.0.push((key, values)),
+        }
+    }
+
+    /// Append a single value to `key`.
+    pub fn push<K: ToString, V: ToString>(&mut self, key: K, value: V) {
+        self.extend(key, vec![value.to_string()])
+    }
+
+    /// First value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).first().map(|x| x.as_str())
+    }
+
+    /// Every value of `key`, empty when it is absent.
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map_or(&[], |(_, values)| values.as_slice())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("ID")
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.get("Name")
+    }
+
+    pub fn signature_desc(&self) -> Option<&str> {
+        self.get("signature_desc")
+    }
+
+    /// Date of the InterProScan run, as written by InterProScan (`DD-MM-YYYY`).
+    pub fn date(&self) -> Option<&str> {
+        self.get("date")
+    }
+
+    /// Match status; InterProScan always writes `T`.
+    pub fn status(&self) -> Option<&str> {
+        self.get("status")
+    }
+
+    /// MD5 checksum of the protein sequence (polypeptide lines only).
+    pub fn md5(&self) -> Option<&str> {
+        self.get("md5")
+    }
+
+    pub fn dbxref(&self) -> &[String] {
+        self.get_all("Dbxref")
+    }
+
+    pub fn ontology_term(&self) -> &[String] {
+        self.get_all("Ontology_term")
+    }
+
+    /// The parsed `Target` attribute, `None` when it is absent or malformed.
+    pub fn target(&self) -> Option<Target> {
+        let mut fields = self.get("Target")?.split_whitespace();
+        let id = fields.next()?.to_string();
+        let start = fields.next()?.parse().ok()?;
+        let end = fields.next()?.parse().ok()?;
+
+        Some(Target { id, start, end })
+    }
+
+    /// Description of the InterPro entry, from TSV, XML and JSON input.
+    pub fn interpro_desc(&self) -> Option<&str> {
+        self.get("interpro_desc")
+    }
+
+    /// Type of the InterPro entry (`DOMAIN`, `FAMILY`, ...), from XML and JSON input.
+    pub fn interpro_type(&self) -> Option<&str> {
+        self.get("interpro_type")
+    }
+
+    /// Region of the HMM covered by the match (`hmm_start`, `hmm_end`).
+    pub fn hmm_range(&self) -> Option<(u64, u64)> {
+        Some((
+            self.get("hmm_start")?.parse().ok()?,
+            self.get("hmm_end")?.parse().ok()?,
+        ))
+    }
+
+    /// Whether the HMM was matched in full: `COMPLETE`, `N_TERMINAL_COMPLETE`, ...
+    pub fn hmm_bounds(&self) -> Option<&str> {
+        self.get("hmm_bounds")
+    }
+
+    /// Envelope of an HMMER match (`env_start`, `env_end`).
+    pub fn envelope(&self) -> Option<(u64, u64)> {
+        Some((
+            self.get("env_start")?.parse().ok()?,
+            self.get("env_end")?.parse().ok()?,
+        ))
+    }
+
+    /// Alignment of the sequence to the profile, as reported by profile-based sources.
+    pub fn alignment(&self) -> Option<&str> {
+        self.get("alignment")
+    }
+
+    /// Format as a GFF3 attribute column that [`Attributes::parse`] reads back unchanged.
+    ///
+    /// Like InterProScan, cross-references and GO terms are written in double quotes.
+    pub fn to_gff_column(&self) -> String {
+        self.0
+            .iter()
+            .map(|(key, values)| {
+                let multi_valued = MULTI_VALUED.contains(&key.as_str());
+                let quoted = key == "Dbxref" || key == "Ontology_term";
+                let values = values
+                    .iter()
+                    .map(|value| {
+                        let value = percent_encode(value, multi_valued);
+                        if quoted {
+                            format!("\"{}\"", value)
+                        } else {
+                            value
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(",");
+
+                format!("{}={}", percent_encode(key, true), values)
+            })
+            .collect::<Vec<String>>()
+            .join(";")
+    }
+}
+
+/// Split `s` on `sep`, ignoring separators inside double quotes.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == sep && !in_quotes {
+            fields.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    fields.push(&s[start..]);
+
+    fields
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    s.strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+/// Decode GFF3 `%XX` escapes. Malformed escapes are kept as they are.
+pub fn percent_decode(s: &str) -> String {
+    if !s.contains('%') {
+        return s.to_string();
+    }
+
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).expect("ascii hex digits");
+            decoded.push(u8::from_str_radix(hex, 16).expect("ascii hex digits"));
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escape the characters GFF3 reserves in column 9, and commas when `comma` is set.
+/// Double quotes are escaped too so that values are never taken for quoted ones.
+pub fn percent_encode(s: &str, comma: bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ';' | '=' | '&' | '%' | '"' => encoded.push_str(&format!("%{:02X}", c as u32)),
+            ',' if comma => encoded.push_str("%2C"),
+            c if c.is_ascii_control() => encoded.push_str(&format!("%{:02X}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+
+    encoded
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.id, self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod test_attributes {
+    use super::*;
+
+    #[test]
+    fn parse_interproscan() {
+        let attributes = Attributes::parse(
+            "date=21-02-2022;Target=kfl00063_0090_v1.1 99 184;ID=match$2_99_184;\
+             signature_desc=BRCT domain, a BRCA1 C-terminus domain;Name=PS50172;status=T;\
+             Dbxref=\"InterPro:IPR001357\"",
+        );
+
+        assert_eq!(attributes.date(), Some("21-02-2022"));
+        assert_eq!(attributes.id(), Some("match$2_99_184"));
+        assert_eq!(attributes.name(), Some("PS50172"));
+        assert_eq!(
+            attributes.signature_desc(),
+            Some("BRCT domain, a BRCA1 C-terminus domain")
+        );
+        assert_eq!(attributes.status(), Some("T"));
+        assert_eq!(attributes.dbxref(), &["InterPro:IPR001357".to_string()]);
+        assert_eq!(
+            attributes.target(),
+            Some(Target {
+                id: "kfl00063_0090_v1.1".to_string(),
+                start: 99,
+                end: 184
+            })
+        );
+        assert_eq!(attributes.md5(), None);
+    }
+
+    #[test]
+    fn multi_valued() {
+        let attributes = Attributes::parse(
+            "Dbxref=\"InterPro:IPR001357\",\"Reactome:R-HSA-5693565\";Ontology_term=\"GO:0005515\"",
+        );
+
+        assert_eq!(
+            attributes.dbxref(),
+            &[
+                "InterPro:IPR001357".to_string(),
+                "Reactome:R-HSA-5693565".to_string()
+            ]
+        );
+        assert_eq!(attributes.ontology_term(), &["GO:0005515".to_string()]);
+    }
+
+    #[test]
+    fn escapes() {
+        let attributes = Attributes::parse("Name=a%3Bb;Note=x%2Cy,z;signature_desc=k=v %");
+
+        assert_eq!(attributes.name(), Some("a;b"));
+        assert_eq!(
+            attributes.get_all("Note"),
+            &["x,y".to_string(), "z".to_string()]
+        );
+        assert_eq!(attributes.signature_desc(), Some("k=v %"));
+    }
+
+    #[test]
+    fn gff_column() {
+        let column = "date=21-02-2022;signature_desc=BRCT domain, a;Name=a%3Bb;\
+                      Dbxref=\"InterPro:IPR001357\",\"x%2Cy\";note%3D=100%25\tdone";
+        let attributes = Attributes::parse(column);
+
+        let written = attributes.to_gff_column();
+        assert_eq!(
+            written,
+            "date=21-02-2022;signature_desc=BRCT domain, a;Name=a%3Bb;\
+             Dbxref=\"InterPro:IPR001357\",\"x%2Cy\";note%3D=100%25%09done"
+        );
+        assert_eq!(Attributes::parse(&written), attributes);
+    }
+
+    #[test]
+    fn invalid() {
+        let attributes = Attributes::parse("Name=a;broken;=b;;");
+        assert_eq!(attributes.name(), Some("a"));
+        assert_eq!(attributes.to_gff_column(), "Name=a");
+    }
+}
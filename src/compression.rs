@@ -0,0 +1,308 @@
+//! Transparent decompression of the input and compression of the output.
+//!
+//! Gzip (bgzip included) is always supported; zstd, bzip2 and xz need the `zstd`, `bzip2`
+//! and `xz` cargo features.
+
+use std::{
+    ffi::OsStr,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+
+use crate::error::Error;
+
+/// Leading bytes of every gzip member, bgzip included.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// Followed by the block size, `1` to `9`, which tells bzip2 apart from text starting
+/// with `BZh`.
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// A compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Recognize a compressed stream from its first bytes.
+    pub fn detect(buf: &[u8]) -> Self {
+        if buf.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if buf.starts_with(ZSTD_MAGIC) {
+            Self::Zstd
+        } else if buf.starts_with(BZIP2_MAGIC)
+            && buf
+                .get(BZIP2_MAGIC.len())
+                .is_some_and(|x| (b'1'..=b'9').contains(x))
+        {
+            Self::Bzip2
+        } else if buf.starts_with(XZ_MAGIC) {
+            Self::Xz
+        } else {
+            Self::None
+        }
+    }
+
+    /// The compression conventionally named by the extension of `p`: `.gz`, `.bgz`,
+    /// `.zst`, `.bz2` or `.xz`.
+    pub fn from_extension<P: AsRef<Path>>(p: &P) -> Self {
+        match p.as_ref().extension().and_then(OsStr::to_str) {
+            Some("gz") | Some("bgz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            Some("bz2") => Self::Bzip2,
+            Some("xz") => Self::Xz,
+            _ => Self::None,
+        }
+    }
+
+    /// Name of the format, such as `gzip`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Bzip2 => "bzip2",
+            Self::Xz => "xz",
+        }
+    }
+
+    fn unsupported(&self) -> Error {
+        Error::Io(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{0} support is not compiled in; build with --features {0}",
+                self.name()
+            ),
+        ))
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Reports failures of a decoder as [`Error::Gzip`] or [`Error::Decompress`] instead of a
+/// plain I/O error.
+pub(crate) struct DecoderReader<R> {
+    compression: Compression,
+    inner: R,
+}
+
+impl<R: Read> DecoderReader<R> {
+    pub(crate) fn new(compression: Compression, inner: R) -> Self {
+        Self { compression, inner }
+    }
+}
+
+impl<R: Read> Read for DecoderReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|err| {
+            let kind = err.kind();
+            let err = match self.compression {
+                Compression::Gzip => Error::Gzip(err),
+                compression => Error::Decompress(compression, err),
+            };
+            io::Error::new(kind, err)
+        })
+    }
+}
+
+/// Decompress `input` on the fly if its first bytes are those of a supported format.
+pub fn decompress<R: BufRead + 'static>(mut input: R) -> Result<Box<dyn BufRead>, Error> {
+    let compression = Compression::detect(input.fill_buf()?);
+    let reader: Box<dyn BufRead> = match compression {
+        Compression::None => Box::new(input),
+        Compression::Gzip => Box::new(BufReader::new(DecoderReader::new(
+            compression,
+            MultiGzDecoder::new(input),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(BufReader::new(DecoderReader::new(
+            compression,
+            zstd::Decoder::with_buffer(input)?,
+        ))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Box::new(BufReader::new(DecoderReader::new(
+            compression,
+            bzip2::bufread::MultiBzDecoder::new(input),
+        ))),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(BufReader::new(DecoderReader::new(
+            compression,
+            xz2::bufread::XzDecoder::new_multi_decoder(input),
+        ))),
+        #[allow(unreachable_patterns)]
+        _ => return Err(compression.unsupported()),
+    };
+
+    Ok(reader)
+}
+
+/// A writer compressing into `W`.
+///
+/// Call [`Encoder::finish`] once done: dropping the encoder instead may silently lose the
+/// end of the stream.
+pub struct Encoder<W: Write>(EncoderInner<W>);
+
+enum EncoderInner<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::write::BzEncoder<W>),
+    #[cfg(feature = "xz")]
+    Xz(xz2::write::XzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(compression: Compression, output: W) -> Result<Self, Error> {
+        let inner = match compression {
+            Compression::None => EncoderInner::None(output),
+            Compression::Gzip => {
+                EncoderInner::Gzip(GzEncoder::new(output, flate2::Compression::default()))
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => EncoderInner::Zstd(zstd::Encoder::new(output, 0)?),
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2 => EncoderInner::Bzip2(bzip2::write::BzEncoder::new(
+                output,
+                bzip2::Compression::default(),
+            )),
+            #[cfg(feature = "xz")]
+            Compression::Xz => EncoderInner::Xz(xz2::write::XzEncoder::new(output, 6)),
+            #[allow(unreachable_patterns)]
+            _ => return Err(compression.unsupported()),
+        };
+
+        Ok(Self(inner))
+    }
+
+    /// Write the end of the compressed stream and return the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self.0 {
+            EncoderInner::None(mut output) => {
+                output.flush()?;
+                Ok(output)
+            }
+            EncoderInner::Gzip(encoder) => encoder.finish(),
+            #[cfg(feature = "zstd")]
+            EncoderInner::Zstd(encoder) => encoder.finish(),
+            #[cfg(feature = "bzip2")]
+            EncoderInner::Bzip2(encoder) => encoder.finish(),
+            #[cfg(feature = "xz")]
+            EncoderInner::Xz(encoder) => encoder.finish(),
+        }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match &mut self.0 {
+            EncoderInner::None(output) => output,
+            EncoderInner::Gzip(encoder) => encoder,
+            #[cfg(feature = "zstd")]
+            EncoderInner::Zstd(encoder) => encoder,
+            #[cfg(feature = "bzip2")]
+            EncoderInner::Bzip2(encoder) => encoder,
+            #[cfg(feature = "xz")]
+            EncoderInner::Xz(encoder) => encoder,
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+#[cfg(test)]
+mod test_compression {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "##gff-version 3\n";
+
+    fn round_trip(compression: Compression) {
+        let mut encoder = Encoder::new(compression, vec![]).unwrap();
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(Compression::detect(&compressed), compression);
+
+        let mut decompressed = String::new();
+        decompress(Cursor::new(compressed))
+            .unwrap()
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, TEXT);
+    }
+
+    #[test]
+    fn gzip() {
+        round_trip(Compression::None);
+        round_trip(Compression::Gzip);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        round_trip(Compression::Zstd);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2() {
+        round_trip(Compression::Bzip2);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn xz() {
+        round_trip(Compression::Xz);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn unsupported() {
+        let err = decompress(Cursor::new(ZSTD_MAGIC.to_vec())).err().unwrap();
+        assert!(matches!(err, Error::Io(err) if err.kind() == io::ErrorKind::Unsupported));
+    }
+
+    #[test]
+    fn plain_bzh() {
+        const TSV: &str = "BZh_0010\tmd5\t300\tPfam\tPF00069\n";
+        assert_eq!(Compression::detect(TSV.as_bytes()), Compression::None);
+        assert_eq!(Compression::detect(b"BZh"), Compression::None);
+
+        let mut read = String::new();
+        decompress(Cursor::new(TSV.as_bytes().to_vec()))
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, TSV);
+    }
+
+    #[test]
+    fn extension() {
+        assert_eq!(
+            Compression::from_extension(&"out.gff3.gz"),
+            Compression::Gzip
+        );
+        assert_eq!(Compression::from_extension(&"out.zst"), Compression::Zstd);
+        assert_eq!(Compression::from_extension(&"out.tsv"), Compression::None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{error, fmt, io};
+
+use crate::compression::Compression;
+
+/// Everything that can go wrong while reading InterProScan output or evaluating an [`Expr`].
+///
+/// Errors tied to a line of the input carry its 1-based `line_no` and the byte `offset`
+/// at which that line starts.
+///
+/// [`Expr`]: crate::Expr
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the input failed.
+    Io(io::Error),
+    /// The input looked gzip-compressed but could not be decompressed.
+    Gzip(io::Error),
+    /// The input looked compressed with zstd, bzip2 or xz but could not be decompressed.
+    Decompress(Compression, io::Error),
+    /// The line does not have as many tab-separated columns as the format requires
+    /// (`expected`, such as 9 for GFF3).
+    MalformedLine {
+        line_no: usize,
+        offset: u64,
+        expected: &'static str,
+        columns: usize,
+    },
+    /// A coordinate or length column is not an integer.
+    InvalidCoordinate {
+        line_no: usize,
+        offset: u64,
+        column: usize,
+        value: String,
+    },
+    /// The score column is neither empty (`.` or `-`) nor a number.
+    InvalidScore {
+        line_no: usize,
+        offset: u64,
+        column: usize,
+        value: String,
+    },
+    /// A sequence of the `##FASTA` section does not match its polypeptide line.
+    InvalidSequence {
+        line_no: usize,
+        offset: u64,
+        id: String,
+        message: String,
+    },
+    /// The XML input is not well-formed or misses something InterProScan always writes.
+    /// `offset` is the byte position the problem was found at.
+    Xml { offset: u64, message: String },
+    /// The JSON input is not well-formed or does not have the layout of InterProScan's.
+    /// `offset` is the byte position of the protein or token in question.
+    Json { offset: u64, message: String },
+    /// An expression could not be parsed. `position` is the 0-based character offset of
+    /// the offending token.
+    Expr { position: usize, message: String },
+    /// A GFF3 match comes after the block of its polypeptide line ended, when the protein
+    /// had already been yielded without it. Reading such input needs buffering.
+    UngroupedMatch {
+        line_no: usize,
+        offset: u64,
+        id: String,
+    },
+    /// A protein was read more than once, for instance from two of the input files.
+    DuplicateId { id: String },
+    /// A source expression matches none of [`SOURCE_NAMES`](crate::utils::SOURCE_NAMES).
+    UnknownSource { expected: Vec<String> },
+}
+
+impl Error {
+    pub(crate) fn expr<S: ToString>(position: usize, message: S) -> Self {
+        Self::Expr {
+            position,
+            message: message.to_string(),
+        }
+    }
+
+    /// The 1-based line of the input this error was raised on, if any.
+    pub fn line_no(&self) -> Option<usize> {
+        match self {
+            Self::MalformedLine { line_no, .. }
+            | Self::InvalidCoordinate { line_no, .. }
+            | Self::InvalidScore { line_no, .. }
+            | Self::InvalidSequence { line_no, .. }
+            | Self::UngroupedMatch { line_no, .. } => Some(*line_no),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Gzip(err) => write!(f, "invalid gzip stream: {}", err),
+            Self::Decompress(compression, err) => {
+                write!(f, "invalid {} stream: {}", compression, err)
+            }
+            Self::MalformedLine {
+                line_no,
+                offset,
+                expected,
+                columns,
+            } => write!(
+                f,
+                "line {} (byte {}): expected {} tab-separated columns, found {}",
+                line_no, offset, expected, columns
+            ),
+            Self::InvalidCoordinate {
+                line_no,
+                offset,
+                column,
+                value,
+            } => write!(
+                f,
+                "line {} (byte {}): column {} is not a valid coordinate: {:?}",
+                line_no, offset, column, value
+            ),
+            Self::InvalidScore {
+                line_no,
+                offset,
+                column,
+                value,
+            } => write!(
+                f,
+                "line {} (byte {}): column {} is not a valid score: {:?}",
+                line_no, offset, column, value
+            ),
+            Self::InvalidSequence {
+                line_no,
+                offset,
+                id,
+                message,
+            } => write!(
+                f,
+                "line {} (byte {}): sequence of {} has {}",
+                line_no, offset, id, message
+            ),
+            Self::UngroupedMatch {
+                line_no,
+                offset,
+                id,
+            } => write!(
+                f,
+                "line {} (byte {}): match of {} after its protein was already read; \
+                 matches are not grouped by sequence, read with buffering (--buffered)",
+                line_no, offset, id
+            ),
+            Self::DuplicateId { id } => write!(f, "protein {} was read more than once", id),
+            Self::Xml { offset, message } => {
+                write!(f, "byte {}: invalid XML: {}", offset, message)
+            }
+            Self::Json { offset, message } => {
+                write!(f, "byte {}: invalid JSON: {}", offset, message)
+            }
+            Self::Expr { position, message } => {
+                write!(f, "ParseError at position {}: {}", position, message)
+            }
+            Self::UnknownSource { expected } => write!(
+                f,
+                "Invalid source expr. Please select from [{}]",
+                expected.join(" ")
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) | Self::Gzip(err) | Self::Decompress(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // decoders wrap their own failures into the io::Error they return, see `DecoderReader`
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().expect("checked above");
+            return *inner.downcast::<Error>().expect("checked above");
+        }
+
+        Self::Io(err)
+    }
+}
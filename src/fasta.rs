@@ -0,0 +1,204 @@
+use std::{collections::HashMap, io::BufRead};
+
+use crate::error::Error;
+use crate::parser::Expr;
+use crate::records::{DomainRecord, GeneRecord};
+
+/// Residues per line of FASTA output.
+pub const LINE_WIDTH: usize = 60;
+
+/// Protein sequences by id, read from a FASTA file such as the one given to InterProScan.
+#[derive(Debug, Clone, Default)]
+pub struct ProteinSequences(HashMap<String, String>);
+
+impl ProteinSequences {
+    /// The id of a sequence is the first word of its header. Lines before the first header
+    /// are ignored, and only the first of several sequences with the same id is kept.
+    pub fn from_fasta<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut sequences = HashMap::new();
+        let mut current: Option<(String, String)> = None;
+
+        for line in reader.lines() {
+            let line = line?;
+
+            if let Some(header) = line.strip_prefix('>') {
+                if let Some((id, sequence)) = current.take() {
+                    insert_sequence(&mut sequences, id, sequence);
+                }
+                let id = header.split_whitespace().next().unwrap_or_default();
+                current = Some((id.to_string(), String::new()));
+            } else if let Some((_, sequence)) = current.as_mut() {
+                sequence.extend(line.chars().filter(|x| !x.is_whitespace()));
+            }
+        }
+
+        if let Some((id, sequence)) = current {
+            insert_sequence(&mut sequences, id, sequence);
+        }
+
+        Ok(Self(sequences))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.0.get(id).map(|x| x.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn insert_sequence(sequences: &mut HashMap<String, String>, id: String, sequence: String) {
+    if sequences.contains_key(&id) {
+        warn!("ignored a duplicate sequence for {}", id);
+        return;
+    }
+    sequences.insert(id, sequence);
+}
+
+/// Formats [`GeneRecord`]s as FASTA, either whole proteins or the subsequence of each domain.
+#[derive(Debug, Clone, Default)]
+pub struct FastaFormatter {
+    domains: bool,
+    domain_expr: Option<Expr>,
+    flank: u64,
+}
+
+impl FastaFormatter {
+    /// Writes whole proteins by default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write one entry per domain, with a header like `>id|PF12738|190-254`.
+    pub fn with_domains(mut self, domains: bool) -> Self {
+        self.domains = domains;
+        self
+    }
+
+    /// Only write domains whose name or InterPro accessions match `domain_expr`. All
+    /// domains by default.
+    pub fn with_domain_expr(mut self, domain_expr: Option<Expr>) -> Self {
+        self.domain_expr = domain_expr;
+        self
+    }
+
+    /// Extend domains by `flank` residues on both sides, within the protein. The header
+    /// gives the extended region.
+    pub fn with_flank(mut self, flank: u64) -> Self {
+        self.flank = flank;
+        self
+    }
+
+    /// FASTA lines of `gene_record`, whose sequence is `sequence`.
+    pub fn to_fasta_lines(
+        &self,
+        gene_record: &GeneRecord,
+        sequence: &str,
+    ) -> Result<Vec<String>, Error> {
+        let mut lines = vec![];
+
+        if !self.domains {
+            push_entry(&mut lines, &gene_record.id, sequence);
+            return Ok(lines);
+        }
+
+        for domain in gene_record.iter_domains() {
+            if !self.is_extracted(domain)? {
+                continue;
+            }
+
+            let length = sequence.len() as u64;
+            let start = domain.start.saturating_sub(self.flank).max(1);
+            let end = (domain.end + self.flank).min(length);
+            let subsequence = match sequence.get(start as usize - 1..end as usize) {
+                Some(subsequence) if start <= end => subsequence,
+                _ => continue,
+            };
+
+            let header = format!(
+                "{}|{}|{}-{}",
+                gene_record.id, domain.domain_name, start, end
+            );
+            push_entry(&mut lines, &header, subsequence);
+        }
+
+        Ok(lines)
+    }
+
+    fn is_extracted(&self, domain: &DomainRecord) -> Result<bool, Error> {
+        match &self.domain_expr {
+            Some(expr) => expr.matches_domain(domain),
+            None => Ok(true),
+        }
+    }
+}
+
+fn push_entry(lines: &mut Vec<String>, header: &str, sequence: &str) {
+    lines.push(format!(">{}", header));
+    for chunk in sequence.as_bytes().chunks(LINE_WIDTH) {
+        lines.push(String::from_utf8_lossy(chunk).into_owned());
+    }
+}
+
+#[cfg(test)]
+mod test_fasta {
+    use super::*;
+
+    fn record() -> GeneRecord {
+        let mut record = GeneRecord::new("prot1".to_string(), 1, 70);
+        record.push_domain(DomainRecord::new("Pfam", 3, 8, "PF12738", "BRCT"));
+        record.push_domain(DomainRecord::new("Pfam", 60, 68, "PF00400", "WD40"));
+        record
+    }
+
+    #[test]
+    fn from_fasta() {
+        let fasta = "ignored\n>prot1 description\nMKV\nLL\n\n>prot2\nAA\n>prot1\nWW\n";
+        let sequences = ProteinSequences::from_fasta(fasta.as_bytes()).unwrap();
+
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences.get("prot1"), Some("MKVLL"));
+        assert_eq!(sequences.get("prot2"), Some("AA"));
+    }
+
+    #[test]
+    fn proteins() {
+        let sequence = "A".repeat(70);
+        let lines = FastaFormatter::new()
+            .to_fasta_lines(&record(), &sequence)
+            .unwrap();
+
+        assert_eq!(lines, vec![">prot1", &sequence[..60], &sequence[60..]]);
+    }
+
+    #[test]
+    fn domains() {
+        let sequence: String = ('A'..='Z').cycle().take(70).collect();
+        let lines = FastaFormatter::new()
+            .with_domains(true)
+            .to_fasta_lines(&record(), &sequence)
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                ">prot1|PF12738|3-8",
+                "CDEFGH",
+                ">prot1|PF00400|60-68",
+                "HIJKLMNOP"
+            ]
+        );
+
+        let lines = FastaFormatter::new()
+            .with_domains(true)
+            .with_domain_expr(Some(Expr::from_string("PF00400").unwrap()))
+            .with_flank(5)
+            .to_fasta_lines(&record(), &sequence)
+            .unwrap();
+        assert_eq!(lines, vec![">prot1|PF00400|55-70", "CDEFGHIJKLMNOPQR"]);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::error::Error;
+use crate::parser::{Architecture, Expr};
+use crate::records::{DomainRecord, GeneRecord};
+
+/// The filters shared by every reader.
+///
+/// Readers expose the same `with_*` methods, so a filter rarely needs to be built by hand;
+/// it is mostly useful to configure several readers alike with `with_filter`.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct RecordFilter {
+    pub(crate) id_expr: Option<Expr>,
+    pub(crate) domain_expr: Option<Expr>,
+    pub(crate) interpro_expr: Option<Expr>,
+    pub(crate) go_expr: Option<Expr>,
+    pub(crate) source_expr: Option<Expr>,
+    pub(crate) arch: Option<Architecture>,
+    pub(crate) max_length: Option<u64>,
+    pub(crate) min_length: Option<u64>,
+    pub(crate) max_evalue: Option<f64>,
+    pub(crate) min_score: Option<f64>,
+}
+
+/// Implements the `with_*` methods of [`RecordFilter`], either on the filter itself or on a
+/// reader holding one in its `filter` field.
+macro_rules! filter_methods {
+    () => {
+        filter_methods!(@impl);
+    };
+    (filter) => {
+        /// Replace every filter at once.
+        pub fn with_filter(mut self, filter: $crate::filter::RecordFilter) -> Self {
+            self.filter = filter;
+            self
+        }
+
+        filter_methods!(@impl filter);
+    };
+    (@impl $($filter:ident)?) => {
+        /// Keep only proteins whose id matches `expr`.
+        pub fn with_id_expr(mut self, expr: Option<$crate::parser::Expr>) -> Self {
+            self$(.$filter)?.id_expr = expr;
+            self
+        }
+
+        /// Keep only proteins whose domain names match `expr`.
+        pub fn with_domain_expr(mut self, expr: Option<$crate::parser::Expr>) -> Self {
+            self$(.$filter)?.domain_expr = expr;
+            self
+        }
+
+        /// Keep only proteins whose InterPro accessions (`IPR001357`) match `expr`.
+        pub fn with_interpro_expr(mut self, expr: Option<$crate::parser::Expr>) -> Self {
+            self$(.$filter)?.interpro_expr = expr;
+            self
+        }
+
+        /// Keep only proteins whose GO terms (`GO:0005515`) match `expr`.
+        pub fn with_go_expr(mut self, expr: Option<$crate::parser::Expr>) -> Self {
+            self$(.$filter)?.go_expr = expr;
+            self
+        }
+
+        /// Drop the domains whose source (analysis) does not match `expr`.
+        pub fn with_source_expr(mut self, expr: Option<$crate::parser::Expr>) -> Self {
+            self$(.$filter)?.source_expr = expr;
+            self
+        }
+
+        /// Keep only proteins whose domains, N- to C-terminal, match `arch`. Only the
+        /// domains kept by the other filters, such as `with_source_expr`, are considered.
+        pub fn with_arch(mut self, arch: Option<$crate::parser::Architecture>) -> Self {
+            self$(.$filter)?.arch = arch;
+            self
+        }
+
+        /// Drop proteins longer than `length` residues.
+        pub fn with_max_length(mut self, length: Option<u64>) -> Self {
+            self$(.$filter)?.max_length = length;
+            self
+        }
+
+        /// Drop proteins shorter than `length` residues.
+        pub fn with_min_length(mut self, length: Option<u64>) -> Self {
+            self$(.$filter)?.min_length = length;
+            self
+        }
+
+        /// Drop matches whose E-value is above `evalue`. Matches from sources that do not
+        /// report E-values are kept.
+        pub fn with_max_evalue(mut self, evalue: Option<f64>) -> Self {
+            self$(.$filter)?.max_evalue = evalue;
+            self
+        }
+
+        /// Drop matches whose profile score is below `score`. Matches from sources that do
+        /// not report profile scores are kept.
+        pub fn with_min_score(mut self, score: Option<f64>) -> Self {
+            self$(.$filter)?.min_score = score;
+            self
+        }
+    };
+}
+
+pub(crate) use filter_methods;
+
+impl RecordFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    filter_methods!();
+
+    /// Whether the protein `id` passes the id expression.
+    pub fn matches_id(&self, id: &str) -> Result<bool, Error> {
+        match &self.id_expr {
+            Some(expr) => expr.matches(&[id]),
+            None => Ok(true),
+        }
+    }
+
+    pub fn is_in_length_range(&self, gene_record: &GeneRecord) -> bool {
+        if let Some(max_length) = self.max_length {
+            if gene_record.length > max_length {
+                return false;
+            }
+        }
+
+        if let Some(min_length) = self.min_length {
+            if gene_record.length < min_length {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Whether `domain` passes the E-value and score thresholds.
+    pub fn is_significant(&self, domain: &DomainRecord) -> bool {
+        if let (Some(max_evalue), Some(evalue)) = (self.max_evalue, domain.evalue()) {
+            if evalue > max_evalue {
+                return false;
+            }
+        }
+
+        if let (Some(min_score), Some(score)) = (self.min_score, domain.profile_score()) {
+            if score < min_score {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Apply every filter to a protein that was read in one go.
+    pub fn apply(&self, mut gene_record: GeneRecord) -> Result<Option<GeneRecord>, Error> {
+        if !self.matches_id(&gene_record.id)? || !self.is_in_length_range(&gene_record) {
+            return Ok(None);
+        }
+
+        gene_record.retain_domains(|domain| self.is_significant(domain));
+        Ok(self.finalize(gene_record))
+    }
+
+    /// Apply the filters that need every domain of a protein: `None` when an expression or
+    /// the architecture rejects it, otherwise the protein with the domains of other sources
+    /// removed.
+    pub fn finalize(&self, gene_record: GeneRecord) -> Option<GeneRecord> {
+        if let Some(expr) = &self.domain_expr {
+            if !expr.matches_domains(&gene_record).unwrap_or_default() {
+                return None;
+            }
+        }
+
+        if let Some(expr) = &self.interpro_expr {
+            if !expr.matches_interpro(&gene_record).unwrap_or_default() {
+                return None;
+            }
+        }
+
+        if let Some(expr) = &self.go_expr {
+            if !expr.matches_go(&gene_record).unwrap_or_default() {
+                return None;
+            }
+        }
+
+        let gene_record = gene_record.filter_by_source_expr(&self.source_expr);
+        if let Some(arch) = &self.arch {
+            if !arch.matches(&gene_record) {
+                return None;
+            }
+        }
+
+        Some(gene_record)
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::HashMap,
+    io::BufRead,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::Error;
+use crate::records::GeneRecord;
+
+/// GO_REF for "Gene Ontology annotation based on InterPro", i.e. InterPro2GO.
+pub const INTERPRO2GO_REF: &str = "GO_REF:0000002";
+
+/// The three sub-ontologies of GO, column 9 of GAF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aspect {
+    MolecularFunction,
+    BiologicalProcess,
+    CellularComponent,
+}
+
+impl Aspect {
+    /// Parse an OBO `namespace` such as `biological_process`.
+    pub fn from_namespace(namespace: &str) -> Option<Self> {
+        match namespace {
+            "molecular_function" => Some(Self::MolecularFunction),
+            "biological_process" => Some(Self::BiologicalProcess),
+            "cellular_component" => Some(Self::CellularComponent),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> char {
+        match self {
+            Self::MolecularFunction => 'F',
+            Self::BiologicalProcess => 'P',
+            Self::CellularComponent => 'C',
+        }
+    }
+
+    /// Default GAF 2.2 relation (column 4) for annotations of this aspect.
+    pub fn qualifier(&self) -> &'static str {
+        match self {
+            Self::MolecularFunction => "enables",
+            Self::BiologicalProcess => "involved_in",
+            Self::CellularComponent => "located_in",
+        }
+    }
+}
+
+/// Aspect of every GO term, read from an OBO file such as `go-basic.obo`.
+///
+/// InterProScan's GFF3 only names GO terms, so the ontology is needed to fill the aspect
+/// and qualifier columns of GAF.
+#[derive(Debug, Clone, Default)]
+pub struct GoAspects(HashMap<String, Aspect>);
+
+impl GoAspects {
+    pub fn from_obo<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut aspects = HashMap::new();
+        let mut ids: Vec<String> = vec![];
+        let mut aspect = None;
+        let mut in_term = false;
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.starts_with('[') {
+                if let Some(aspect) = aspect.take() {
+                    for id in ids.drain(..) {
+                        aspects.insert(id, aspect);
+                    }
+                }
+                ids.clear();
+                in_term = line == "[Term]";
+                continue;
+            }
+
+            if !in_term {
+                continue;
+            }
+
+            if let Some(id) = line
+                .strip_prefix("id:")
+                .or_else(|| line.strip_prefix("alt_id:"))
+            {
+                ids.push(id.trim().to_string());
+            } else if let Some(namespace) = line.strip_prefix("namespace:") {
+                aspect = Aspect::from_namespace(namespace.trim());
+            }
+        }
+
+        if let Some(aspect) = aspect {
+            for id in ids {
+                aspects.insert(id, aspect);
+            }
+        }
+
+        Ok(Self(aspects))
+    }
+
+    pub fn get(&self, go_id: &str) -> Option<Aspect> {
+        self.0.get(go_id).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Formats the GO terms of [`GeneRecord`]s as GAF 2.2 lines.
+///
+/// Each protein/GO term pair gives one line, with the InterPro entries the term was
+/// inherited from in the with/from column.
+#[derive(Debug, Clone)]
+pub struct GafFormatter {
+    db: String,
+    taxon: String,
+    evidence_code: String,
+    assigned_by: String,
+    date: String,
+    aspects: GoAspects,
+}
+
+impl GafFormatter {
+    /// `db` is written in column 1 and `taxon` is an NCBI taxon id (`9606` or `taxon:9606`).
+    pub fn new<S: ToString>(db: S, taxon: S, aspects: GoAspects) -> Self {
+        let taxon = taxon.to_string();
+        let taxon = if taxon.starts_with("taxon:") {
+            taxon
+        } else {
+            format!("taxon:{}", taxon)
+        };
+
+        Self {
+            db: db.to_string(),
+            taxon,
+            evidence_code: "IEA".to_string(),
+            assigned_by: "InterPro".to_string(),
+            date: today(),
+            aspects,
+        }
+    }
+
+    /// Defaults to `IEA`.
+    pub fn with_evidence_code<S: ToString>(mut self, evidence_code: S) -> Self {
+        self.evidence_code = evidence_code.to_string();
+        self
+    }
+
+    /// Defaults to `InterPro`.
+    pub fn with_assigned_by<S: ToString>(mut self, assigned_by: S) -> Self {
+        self.assigned_by = assigned_by.to_string();
+        self
+    }
+
+    /// Annotation date as `YYYYMMDD`. Defaults to today.
+    pub fn with_date<S: ToString>(mut self, date: S) -> Self {
+        self.date = date.to_string();
+        self
+    }
+
+    pub fn header(&self) -> String {
+        "!gaf-version: 2.2".to_string()
+    }
+
+    /// GAF lines for `gene_record`, and the GO terms skipped because their aspect is unknown.
+    pub fn to_gaf_lines<'a>(&self, gene_record: &'a GeneRecord) -> (Vec<String>, Vec<&'a str>) {
+        let mut with_from: Vec<(&str, Vec<&str>)> = vec![];
+        for domain in gene_record.iter_domains() {
+            let accessions = domain.interpro_accessions();
+            for term in domain.go_terms() {
+                let index = match with_from.iter().position(|(x, _)| *x == term) {
+                    Some(index) => index,
+                    None => {
+                        with_from.push((term, vec![]));
+                        with_from.len() - 1
+                    }
+                };
+
+                for accession in accessions.iter() {
+                    if !with_from[index].1.contains(accession) {
+                        with_from[index].1.push(accession);
+                    }
+                }
+            }
+        }
+
+        let mut lines = vec![];
+        let mut unknown = vec![];
+        for (term, accessions) in with_from {
+            let aspect = match self.aspects.get(term) {
+                Some(aspect) => aspect,
+                None => {
+                    unknown.push(term);
+                    continue;
+                }
+            };
+
+            let with_from = accessions
+                .iter()
+                .map(|x| format!("InterPro:{}", x))
+                .collect::<Vec<String>>()
+                .join("|");
+
+            lines.push(
+                [
+                    self.db.as_str(),
+                    &gene_record.id,
+                    &gene_record.id,
+                    aspect.qualifier(),
+                    term,
+                    INTERPRO2GO_REF,
+                    &self.evidence_code,
+                    &with_from,
+                    &aspect.code().to_string(),
+                    "",
+                    "",
+                    "protein",
+                    &self.taxon,
+                    &self.date,
+                    &self.assigned_by,
+                    "",
+                    "",
+                ]
+                .join("\t"),
+            );
+        }
+
+        (lines, unknown)
+    }
+}
+
+/// Today's date in UTC as `YYYYMMDD`.
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date.
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test_gaf {
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::records::DomainRecord;
+
+    const OBO: &str = "format-version: 1.2
+
+[Term]
+id: GO:0005515
+name: protein binding
+namespace: molecular_function
+alt_id: GO:0001948
+
+[Term]
+id: GO:0006281
+name: DNA repair
+namespace: biological_process
+
+[Typedef]
+id: part_of
+namespace: external
+";
+
+    #[test]
+    fn obo() {
+        let aspects = GoAspects::from_obo(OBO.as_bytes()).unwrap();
+        assert_eq!(aspects.len(), 3);
+        assert_eq!(aspects.get("GO:0001948"), Some(Aspect::MolecularFunction));
+        assert_eq!(aspects.get("GO:0006281"), Some(Aspect::BiologicalProcess));
+        assert_eq!(aspects.get("part_of"), None);
+    }
+
+    #[test]
+    fn gaf_lines() {
+        let domain = |name: &str, attributes: &str| {
+            DomainRecord::new("Pfam", 1, 10, name, "desc")
+                .with_attributes(Attributes::parse(attributes))
+        };
+        let mut record = GeneRecord::new("prot1".to_string(), 1, 100);
+        record.push_domain(domain(
+            "PF00001",
+            "Ontology_term=\"GO:0005515\",\"GO:0006281\";Dbxref=\"InterPro:IPR001357\"",
+        ));
+        record.push_domain(domain(
+            "PF00002",
+            "Ontology_term=\"GO:0005515\",\"GO:9999999\";Dbxref=\"InterPro:IPR036420\"",
+        ));
+
+        let formatter = GafFormatter::new(
+            "UniProtKB",
+            "9606",
+            GoAspects::from_obo(OBO.as_bytes()).unwrap(),
+        )
+        .with_date("20220221");
+        let (lines, unknown) = formatter.to_gaf_lines(&record);
+
+        assert_eq!(unknown, vec!["GO:9999999"]);
+        assert_eq!(
+            lines,
+            vec![
+                "UniProtKB\tprot1\tprot1\tenables\tGO:0005515\tGO_REF:0000002\tIEA\tInterPro:IPR001357|InterPro:IPR036420\tF\t\t\tprotein\ttaxon:9606\t20220221\tInterPro\t\t",
+                "UniProtKB\tprot1\tprot1\tinvolved_in\tGO:0006281\tGO_REF:0000002\tIEA\tInterPro:IPR001357\tP\t\t\tprotein\ttaxon:9606\t20220221\tInterPro\t\t",
+            ]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19044), (2022, 2, 21));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+}
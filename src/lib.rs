@@ -0,0 +1,49 @@
+//! Read and filter the GFF3, TSV, XML and JSON output of
+//! [InterProScan](https://interproscan-docs.readthedocs.io/).
+//!
+//! [`InterproGffReader`] is a builder over any [`std::io::BufRead`]: configure the filters
+//! with the `with_*` methods and collect the surviving proteins with
+//! [`InterproGffReader::finish`], or stream them in constant memory with
+//! [`InterproGffReader::records`]. Filters are written in the small boolean language
+//! implemented by [`Expr`]. [`InterproTsvReader`], [`InterproXmlReader`] and
+//! [`InterproJsonReader`] read the other formats into the same records with the same options, and [`detect_format`] tells the
+//! formats apart.
+//!
+//! ```no_run
+//! use interproscan_reader::{read_with_gz, Expr, InterproGffReader};
+//!
+//! let input = read_with_gz(&"proteins.gff3.gz")?;
+//! let records = InterproGffReader::new(input)
+//!     .with_domain_expr(Some(Expr::from_string("PF12738 & !PF16589")?))
+//!     .finish()?;
+//!
+//! for record in records {
+//!     println!("{}\t{}", record.id, record.length);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[macro_use]
+extern crate log;
+
+pub mod attributes;
+pub mod compression;
+pub mod error;
+pub mod fasta;
+pub mod filter;
+pub mod gaf;
+pub mod parser;
+pub mod reader;
+pub mod records;
+pub mod utils;
+
+pub use attributes::Attributes;
+pub use compression::Compression;
+pub use error::Error;
+pub use filter::RecordFilter;
+pub use parser::{Architecture, Expr};
+pub use reader::{
+    detect_format, read_with_gz, InputFormat, InterproGffReader, InterproJsonReader,
+    InterproTsvReader, InterproXmlReader, Records, Strictness,
+};
+pub use records::{sort_records, DomainRecord, GeneRecord, SortKey};
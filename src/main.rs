@@ -2,18 +2,24 @@
 extern crate log;
 
 use comfy_table::Table;
-use std::io::BufRead;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::{env::set_var, error::Error};
 use structopt::StructOpt;
 
 mod opt;
-mod parser;
-mod reader;
-mod records;
-mod utils;
+mod output;
 
-use crate::opt::{LogLevel, Opt};
-use crate::parser::Expr;
+use crate::opt::{LogLevel, Opt, SortBy};
+use crate::output::{Output, Spool};
+use interproscan_reader::fasta::{FastaFormatter, ProteinSequences};
+use interproscan_reader::gaf::{GafFormatter, GoAspects};
+use interproscan_reader::{
+    detect_format, read_with_gz, sort_records, utils, Architecture, Expr, GeneRecord, InputFormat,
+    InterproGffReader, InterproJsonReader, InterproTsvReader, InterproXmlReader, RecordFilter,
+    Records, SortKey, Strictness,
+};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let opt = Opt::from_args();
@@ -31,55 +37,561 @@ fn main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init_timed();
     debug!("{:?}", opt);
 
-    let input = opt.input;
-    let source_expr = opt
-        .source_expr
-        .map(|s| Expr::from_string(&s).expect("Invalid source expr"));
+    let outformat = opt.out_format.unwrap_or(opt::OutputFormat::ID);
+    let gaf = match outformat {
+        opt::OutputFormat::GAF => Some(gaf_formatter(&opt)?),
+        _ => None,
+    };
+    let fasta = match outformat {
+        opt::OutputFormat::FASTA => Some(fasta_output(&opt)?),
+        _ => None,
+    };
 
-    utils::validate_source_expr(&source_expr)?;
+    let inputs = expand_inputs(&opt.input)?;
+    // ids, GO terms, InterPro accessions and sources have no fields
+    let tag_expr = |s: Option<String>| {
+        s.map(|s| Expr::from_string(&s).and_then(Expr::without_fields))
+            .transpose()
+    };
+    let source_expr = tag_expr(opt.source_expr)?;
 
-    let bufreader: Box<dyn BufRead> = reader::read_with_gz(&input)?;
+    utils::validate_source_expr(&source_expr)?;
 
-    let reader = reader::InterproGffReader::new(bufreader)
-        .with_comment(opt.comment)
+    let filter = RecordFilter::new()
         .with_max_length(opt.max_length)
         .with_min_length(opt.min_length)
-        .with_id_expr(
-            opt.id_expr
-                .map(|s| Expr::from_string(&s).expect("Invalid id expr")),
-        )
-        .with_domain_expr(
-            opt.domain_expr
-                .map(|s| Expr::from_string(&s).expect("Invalid domain expr")),
-        )
-        .with_source_expr(source_expr);
-
-    let records = reader.finish()?;
+        .with_max_evalue(opt.max_evalue)
+        .with_min_score(opt.min_score)
+        .with_id_expr(tag_expr(opt.id_expr)?)
+        .with_domain_expr(opt.domain_expr.map(|s| Expr::from_string(&s)).transpose()?)
+        .with_interpro_expr(tag_expr(opt.interpro_expr)?)
+        .with_go_expr(tag_expr(opt.go_expr)?)
+        .with_source_expr(source_expr)
+        .with_arch(
+            opt.arch
+                .map(|s| Architecture::from_string(&s))
+                .transpose()?,
+        );
+    let options = ReadOptions {
+        informat: opt.in_format.map(|informat| match informat {
+            opt::InputFormat::GFF3 => InputFormat::Gff3,
+            opt::InputFormat::TSV => InputFormat::Tsv,
+            opt::InputFormat::XML => InputFormat::Xml,
+            opt::InputFormat::JSON => InputFormat::Json,
+        }),
+        comment: opt.comment,
+        sequences: reads_sequences(outformat, fasta.as_ref(), opt.sequences),
+        filter,
+        buffered: opt.buffered,
+        sorted: opt.sort.is_some(),
+        strictness: if opt.lenient {
+            Strictness::Lenient
+        } else {
+            Strictness::Strict
+        },
+    };
+    let mut records = Inputs::new(inputs, &options);
 
-    let outformat = opt.out_format.unwrap_or(opt::OutputFormat::ID);
+    let mut out = Output::create(opt.output.as_deref())?;
+    let written = match opt.sort {
+        Some(sort_by) => {
+            let mut sorted = records.by_ref().collect::<Result<Vec<_>, _>>()?;
+            let key = match sort_by {
+                SortBy::ID => SortKey::Id,
+                SortBy::LENGTH => SortKey::Length,
+                SortBy::DOMAINS => SortKey::DomainCount,
+            };
+            sort_records(&mut sorted, key);
+            write_records(
+                sorted.into_iter().map(Ok),
+                &mut out,
+                outformat,
+                gaf.as_ref(),
+                fasta.as_ref(),
+            )
+        }
+        None => write_records(
+            records.by_ref(),
+            &mut out,
+            outformat,
+            gaf.as_ref(),
+            fasta.as_ref(),
+        ),
+    };
+    match written.and_then(|_| Ok(out.finish()?)) {
+        // the reader of the output is gone, as in `ir ... | head`
+        Err(err) if output::is_broken_pipe(err.as_ref()) => return Ok(()),
+        written => written?,
+    }
+
+    if records.duplicates > 0 {
+        warn!(
+            "skipped {} proteins read more than once",
+            records.duplicates
+        );
+    }
+
+    Ok(())
+}
+
+/// Expand the glob patterns among `patterns`, which shells leave alone when quoted or
+/// when the argument list would be too long.
+fn expand_inputs(patterns: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut inputs = vec![];
+
+    for pattern in patterns {
+        let text = pattern.to_string_lossy();
+        if text.contains(['*', '?', '[']) {
+            let matched = glob::glob(&text)?.collect::<Result<Vec<_>, _>>()?;
+            if matched.is_empty() {
+                return Err(format!("no input file matches {}", text).into());
+            }
+            inputs.extend(matched);
+        } else if text != "-" && !pattern.exists() {
+            return Err(format!("input file {} does not exist", text).into());
+        } else {
+            inputs.push(pattern.clone());
+        }
+    }
+
+    if inputs.iter().filter(|x| x.as_os_str() == "-").count() > 1 {
+        return Err("standard input can only be read once".into());
+    }
+
+    Ok(inputs)
+}
+
+/// How every input file is read.
+struct ReadOptions {
+    /// Detected for each input when `None`.
+    informat: Option<InputFormat>,
+    comment: char,
+    sequences: bool,
+    filter: RecordFilter,
+    /// `--buffered` was given.
+    buffered: bool,
+    /// Every record is held for sorting anyway.
+    sorted: bool,
+    strictness: Strictness,
+}
+
+fn open_records(
+    path: &Path,
+    options: &ReadOptions,
+) -> Result<Box<dyn Records>, interproscan_reader::Error> {
+    let mut bufreader: Box<dyn BufRead> = read_with_gz(&path)?;
+    let informat = match options.informat {
+        Some(informat) => informat,
+        None => detect_format(&mut bufreader)?,
+    };
+    debug!("reading {:?} as {:?}", path, informat);
+
+    // every protein of XML and JSON is complete in itself, and the parsers cannot resync
+    // after an error
+    if matches!(informat, InputFormat::Xml | InputFormat::Json) {
+        if options.buffered {
+            warn!(
+                "--buffered has no effect on {:?} input {:?}",
+                informat, path
+            );
+        }
+        if options.strictness == Strictness::Lenient {
+            warn!(
+                "--lenient has no effect on {:?} input {:?}: malformed input still fails",
+                informat, path
+            );
+        }
+    }
+    let buffering = options.buffered || options.sorted;
+
+    let filter = options.filter.clone();
+    let records: Box<dyn Records> = match informat {
+        InputFormat::Gff3 => Box::new(
+            InterproGffReader::new(bufreader)
+                .with_comment(options.comment)
+                .with_sequences(options.sequences)
+                .with_filter(filter)
+                .with_buffering(buffering)
+                .with_strictness(options.strictness)
+                .records(),
+        ),
+        InputFormat::Tsv => Box::new(
+            InterproTsvReader::new(bufreader)
+                .with_filter(filter)
+                .with_buffering(buffering)
+                .with_strictness(options.strictness)
+                .records(),
+        ),
+        InputFormat::Xml => Box::new(
+            InterproXmlReader::new(bufreader)
+                .with_filter(filter)
+                .records(),
+        ),
+        InputFormat::Json => Box::new(
+            InterproJsonReader::new(bufreader)
+                .with_filter(filter)
+                .records(),
+        ),
+    };
+
+    Ok(records)
+}
+
+/// The records of every input file in turn.
+///
+/// A protein read a second time is an error, or is skipped and counted in `duplicates`
+/// with `--lenient`. The malformed lines of each file are reported once it is read.
+struct Inputs<'a> {
+    paths: std::vec::IntoIter<PathBuf>,
+    options: &'a ReadOptions,
+    current: Option<(PathBuf, Box<dyn Records>)>,
+    seen: HashSet<String>,
+    duplicates: usize,
+}
+
+impl<'a> Inputs<'a> {
+    fn new(paths: Vec<PathBuf>, options: &'a ReadOptions) -> Self {
+        Self {
+            paths: paths.into_iter(),
+            options,
+            current: None,
+            seen: HashSet::new(),
+            duplicates: 0,
+        }
+    }
+}
+
+impl Iterator for Inputs<'_> {
+    type Item = Result<GeneRecord, interproscan_reader::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, records) = match self.current.as_mut() {
+                Some(current) => current,
+                None => {
+                    let path = self.paths.next()?;
+                    let records = match open_records(&path, self.options) {
+                        Ok(records) => records,
+                        Err(err) => {
+                            error!("failed to read {}", path.display());
+                            return Some(Err(err));
+                        }
+                    };
+                    self.current.insert((path, records))
+                }
+            };
+
+            match records.next() {
+                Some(Ok(record)) => {
+                    if self.seen.insert(record.id.clone()) {
+                        return Some(Ok(record));
+                    }
+                    if self.options.strictness == Strictness::Strict {
+                        return Some(Err(interproscan_reader::Error::DuplicateId {
+                            id: record.id,
+                        }));
+                    }
+                    debug!("skipped {} read again from {}", record.id, path.display());
+                    self.duplicates += 1;
+                }
+                Some(Err(err)) => {
+                    error!("failed to read {}", path.display());
+                    return Some(Err(err));
+                }
+                None => {
+                    report_diagnostics(path, records.as_ref());
+                    self.current = None;
+                }
+            }
+        }
+    }
+}
+
+fn gaf_formatter(opt: &Opt) -> Result<GafFormatter, Box<dyn Error>> {
+    let taxon = opt
+        .gaf_taxon
+        .as_ref()
+        .ok_or("GAF output requires --gaf-taxon")?;
+    let obo = opt
+        .go_obo
+        .as_ref()
+        .ok_or("GAF output requires --go-obo to look up the aspect of GO terms")?;
+
+    let aspects = GoAspects::from_obo(read_with_gz(obo)?)?;
+    debug!("read {} GO terms from {:?}", aspects.len(), obo);
+
+    Ok(GafFormatter::new(&opt.gaf_db, taxon, aspects)
+        .with_evidence_code(&opt.gaf_evidence)
+        .with_assigned_by(&opt.gaf_assigned_by))
+}
+
+/// How to write FASTA output, and where to take sequences from when the input has none.
+struct FastaOutput {
+    formatter: FastaFormatter,
+    proteins: Option<ProteinSequences>,
+}
 
+fn fasta_output(opt: &Opt) -> Result<FastaOutput, Box<dyn Error>> {
+    let domain_expr = match &opt.fasta_domains {
+        Some(Some(s)) => Some(Expr::from_string(s)?),
+        _ => None,
+    };
+    let formatter = FastaFormatter::new()
+        .with_domains(opt.fasta_domains.is_some())
+        .with_domain_expr(domain_expr)
+        .with_flank(opt.fasta_flank);
+
+    let proteins = match &opt.proteins {
+        Some(path) => {
+            let proteins = ProteinSequences::from_fasta(read_with_gz(path)?)?;
+            debug!("read {} sequences from {:?}", proteins.len(), path);
+            Some(proteins)
+        }
+        None => None,
+    };
+
+    Ok(FastaOutput {
+        formatter,
+        proteins,
+    })
+}
+
+/// Whether the sequences of the input must be read: always for FASTA output without
+/// `--proteins`, and with `--sequences` for the other formats that write them. Reading
+/// the `##FASTA` section of GFF3 input holds every protein until its end.
+fn reads_sequences(
+    outformat: opt::OutputFormat,
+    fasta: Option<&FastaOutput>,
+    sequences: bool,
+) -> bool {
+    match outformat {
+        opt::OutputFormat::GFF3 | opt::OutputFormat::JSON | opt::OutputFormat::JSONL => sequences,
+        opt::OutputFormat::FASTA => fasta.is_some_and(|x| x.proteins.is_none()),
+        _ => false,
+    }
+}
+
+fn write_records<I>(
+    records: I,
+    out: &mut dyn Write,
+    outformat: opt::OutputFormat,
+    gaf: Option<&GafFormatter>,
+    fasta: Option<&FastaOutput>,
+) -> Result<(), Box<dyn Error>>
+where
+    I: Iterator<Item = Result<GeneRecord, interproscan_reader::Error>>,
+{
     match outformat {
         opt::OutputFormat::ID => {
             for record in records {
-                println!("{}", record.id)
+                writeln!(out, "{}", record?.id)?
             }
         }
         opt::OutputFormat::ALL => {
             let mut table = Table::new();
-            table.set_header(vec!["id", "source", "term_id", "term_desc", "start", "end"]);
+            table.set_header(vec![
+                "id",
+                "source",
+                "term_id",
+                "term_desc",
+                "start",
+                "end",
+                "interpro",
+            ]);
             for record in records {
-                for row in record.to_table_row().iter() {
+                for row in record?.to_table_row().iter() {
                     table.add_row(row);
                 }
             }
 
-            println!("{table}");
+            writeln!(out, "{table}")?;
         }
         opt::OutputFormat::TSV => {
             for record in records {
-                println!("{}", record.to_tsv_line())
+                writeln!(out, "{}", record?.to_tsv_line())?
+            }
+        }
+        opt::OutputFormat::GO => {
+            for record in records {
+                let record = record?;
+                for term in record.go_terms() {
+                    writeln!(out, "{}\t{}", record.id, term)?
+                }
+            }
+        }
+        opt::OutputFormat::PATHWAY => {
+            for record in records {
+                let record = record?;
+                for pathway in record.pathways() {
+                    writeln!(out, "{}\t{}", record.id, pathway)?
+                }
+            }
+        }
+        opt::OutputFormat::GAF => {
+            let gaf = gaf.expect("built for GAF output");
+            let mut unknown_terms = HashSet::new();
+
+            writeln!(out, "{}", gaf.header())?;
+            for record in records {
+                let record = record?;
+                let (lines, unknown) = gaf.to_gaf_lines(&record);
+                for line in lines {
+                    writeln!(out, "{}", line)?
+                }
+                unknown_terms.extend(unknown.into_iter().map(|x| x.to_string()));
+            }
+
+            if !unknown_terms.is_empty() {
+                warn!(
+                    "skipped {} GO terms missing from the OBO file",
+                    unknown_terms.len()
+                );
+            }
+        }
+        opt::OutputFormat::GFF3 => {
+            // InterProScan appends the sequences after every protein; they wait in a
+            // temporary file rather than in memory
+            let mut spool: Option<Spool> = None;
+
+            writeln!(out, "##gff-version 3")?;
+            for record in records {
+                let record = record?;
+                for line in record.to_gff_lines() {
+                    writeln!(out, "{}", line)?
+                }
+                if let Some(sequence) = &record.sequence {
+                    let spool = match &mut spool {
+                        Some(spool) => spool,
+                        None => spool.insert(Spool::create()?),
+                    };
+                    for line in FastaFormatter::new().to_fasta_lines(&record, sequence)? {
+                        writeln!(spool, "{}", line)?
+                    }
+                }
+            }
+
+            if let Some(mut spool) = spool {
+                writeln!(out, "##FASTA")?;
+                spool.copy_to(out)?;
+            }
+        }
+        opt::OutputFormat::JSON => {
+            write!(out, "[")?;
+            for (i, record) in records.enumerate() {
+                let separator = if i == 0 { "\n" } else { ",\n" };
+                write!(out, "{}{}", separator, record?.to_json())?
+            }
+            writeln!(out, "\n]")?;
+        }
+        opt::OutputFormat::JSONL => {
+            for record in records {
+                writeln!(out, "{}", record?.to_json())?
+            }
+        }
+        opt::OutputFormat::FASTA => {
+            let fasta = fasta.expect("built for FASTA output");
+            let mut missing = 0;
+
+            for record in records {
+                let record = record?;
+                let sequence = match &fasta.proteins {
+                    Some(proteins) => proteins.get(&record.id),
+                    None => record.sequence.as_deref(),
+                };
+                let sequence = match sequence {
+                    Some(sequence) if sequence.len() as u64 == record.length => sequence,
+                    Some(sequence) => {
+                        warn!(
+                            "skipped {}: sequence has {} residues where the input has {}",
+                            record.id,
+                            sequence.len(),
+                            record.length
+                        );
+                        continue;
+                    }
+                    None => {
+                        missing += 1;
+                        continue;
+                    }
+                };
+
+                for line in fasta.formatter.to_fasta_lines(&record, sequence)? {
+                    writeln!(out, "{}", line)?
+                }
+            }
+
+            if missing > 0 {
+                warn!("skipped {} proteins without a sequence", missing);
             }
         }
     }
     Ok(())
 }
+
+fn report_diagnostics(path: &Path, records: &dyn Records) {
+    const MAX_REPORTED: usize = 10;
+
+    let skipped_lines = records.skipped_lines();
+    if skipped_lines == 0 {
+        return;
+    }
+
+    eprintln!(
+        "skipped {} malformed lines of {}:",
+        skipped_lines,
+        path.display()
+    );
+    for diagnostic in records.diagnostics().iter().take(MAX_REPORTED) {
+        eprintln!("    {}", diagnostic);
+    }
+    if skipped_lines > MAX_REPORTED {
+        eprintln!("    ... and {} more", skipped_lines - MAX_REPORTED);
+    }
+}
+
+#[cfg(test)]
+mod test_main {
+    use super::*;
+
+    #[test]
+    fn gff_fasta_round_trip() {
+        let sequence = "M".repeat(100);
+        let input = [
+            format!(
+                "a\t.\tpolypeptide\t1\t100\t.\t+\t.\tID=a;md5={:x}",
+                md5::compute(sequence.as_bytes())
+            ),
+            "a\tPfam\tprotein_match\t10\t50\t1.0E-10\t+\t.\tName=PF00001".to_string(),
+            "##FASTA".to_string(),
+            ">a".to_string(),
+            sequence.clone(),
+        ];
+        let path = std::env::temp_dir().join(format!("ir-{}-fasta.gff3", std::process::id()));
+        std::fs::write(&path, input.join("\n")).unwrap();
+
+        let outformat = opt::OutputFormat::GFF3;
+        // the ##FASTA section holds every protein in memory, so it is only read on demand
+        assert!(!reads_sequences(outformat, None, false));
+        let options = ReadOptions {
+            informat: None,
+            comment: '#',
+            sequences: reads_sequences(outformat, None, true),
+            filter: RecordFilter::new(),
+            buffered: false,
+            sorted: false,
+            strictness: Strictness::Strict,
+        };
+        let records = open_records(&path, &options);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut written = vec![];
+        write_records(records.unwrap(), &mut written, outformat, None, None).unwrap();
+        let read_back = InterproGffReader::new(written.as_slice())
+            .with_sequences(true)
+            .finish()
+            .unwrap();
+
+        assert_eq!(read_back.len(), 1);
+        assert_eq!(read_back[0].sequence.as_deref(), Some(sequence.as_str()));
+    }
+}
@@ -11,26 +11,136 @@ pub struct Opt {
     #[structopt(
         long = "input",
         short = "i",
-        help = "Input GFF3 file generated by interproscan"
+        required = true,
+        help = "Input GFF3, TSV, XML or JSON files generated by interproscan, or - for standard input. Glob patterns are expanded and the records of all files are merged"
+    )]
+    pub input: Vec<PathBuf>,
+    #[structopt(
+        long = "informat",
+        possible_values(&InputFormat::variants()),
+        help = "Format of the input. Detected from its first line by default"
+    )]
+    pub in_format: Option<InputFormat>,
+    #[structopt(
+        long = "output",
+        short = "o",
+        help = "Output file, compressed according to its extension (.gz, .zst, .bz2 or .xz). Written to standard output by default"
+    )]
+    pub output: Option<PathBuf>,
+    #[structopt(
+        long = "outformat",
+        possible_values(&OutputFormat::variants()),
+        help = "ID: protein ids, ALL: a table of domains, TSV: one line per protein and domain, GO and PATHWAY: protein/term pairs, GAF: GO annotations, FASTA: sequences, GFF3: the selected proteins and matches, JSON: an array of proteins, JSONL: one protein per line"
     )]
-    pub input: PathBuf,
-    #[structopt(long = "outformat", possible_values(&OutputFormat::variants()))]
     pub out_format: Option<OutputFormat>,
     #[structopt(
         long = "id-expr",
         help = "To select records by transcripts (or gene) ID"
     )]
     pub id_expr: Option<String>,
-    #[structopt(long = "domain-expr", help = "To select records by domain ID")]
+    #[structopt(
+        long = "domain-expr",
+        help = "To select records by domain ID, or by domain fields (e.g. 'source=Pfam & evalue<1e-10')"
+    )]
     pub domain_expr: Option<String>,
+    #[structopt(
+        long = "arch",
+        help = "To select records by the N-to-C order of their domains (e.g. 'PF00069 > PF00433', 'PF00400{4,} .. PF12894$')"
+    )]
+    pub arch: Option<String>,
+    #[structopt(
+        long = "interpro-expr",
+        help = "To select records by InterPro entry accession (e.g. IPR001357)"
+    )]
+    pub interpro_expr: Option<String>,
+    #[structopt(
+        long = "go-expr",
+        help = "To select records by GO term (e.g. GO:0005515). Requires interproscan --goterms"
+    )]
+    pub go_expr: Option<String>,
     #[structopt(long = "source-expr", help = "Filter output by source name")]
     pub source_expr: Option<String>,
+    #[structopt(
+        long = "go-obo",
+        help = "Gene Ontology OBO file (e.g. go-basic.obo) used to look up the aspect of GO terms for GAF output"
+    )]
+    pub go_obo: Option<PathBuf>,
+    #[structopt(
+        long = "gaf-db",
+        default_value = "UniProtKB",
+        help = "DB column of GAF output"
+    )]
+    pub gaf_db: String,
+    #[structopt(
+        long = "gaf-taxon",
+        help = "NCBI taxon id of the proteins for GAF output (e.g. 9606)"
+    )]
+    pub gaf_taxon: Option<String>,
+    #[structopt(
+        long = "gaf-evidence",
+        default_value = "IEA",
+        help = "Evidence code of GAF output"
+    )]
+    pub gaf_evidence: String,
+    #[structopt(
+        long = "gaf-assigned-by",
+        default_value = "InterPro",
+        help = "Assigned-by column of GAF output"
+    )]
+    pub gaf_assigned_by: String,
+    #[structopt(
+        long = "proteins",
+        help = "Protein FASTA file, optionally gzipped, to take sequences from for FASTA output. Sequences in the input are used by default"
+    )]
+    pub proteins: Option<PathBuf>,
+    #[structopt(
+        long = "fasta-domains",
+        help = "Write the sequence of each domain for FASTA output instead of whole proteins, optionally only domains whose name or InterPro accession matches this expression"
+    )]
+    pub fasta_domains: Option<Option<String>>,
+    #[structopt(
+        long = "fasta-flank",
+        default_value = "0",
+        help = "Residues added on both sides of domains with --fasta-domains"
+    )]
+    pub fasta_flank: u64,
     #[structopt(long = "comment", default_value = "#")]
     pub comment: char,
     #[structopt(long = "min-length")]
     pub min_length: Option<u64>,
     #[structopt(long = "max-length")]
     pub max_length: Option<u64>,
+    #[structopt(
+        long = "max-evalue",
+        help = "Drop matches with a larger E-value (Pfam, CDD, SUPERFAMILY, Gene3D, ...)"
+    )]
+    pub max_evalue: Option<f64>,
+    #[structopt(
+        long = "min-score",
+        help = "Drop matches with a smaller profile score (ProSiteProfiles, HAMAP)"
+    )]
+    pub min_score: Option<f64>,
+    #[structopt(
+        long = "buffered",
+        help = "Keep all records in memory until the end of input. Use when GFF3 or TSV matches are not grouped by sequence"
+    )]
+    pub buffered: bool,
+    #[structopt(
+        long = "sequences",
+        help = "Read the ##FASTA section of GFF3 input so that GFF3, JSON and JSONL output keep the sequences. Holds every protein until the end of the input"
+    )]
+    pub sequences: bool,
+    #[structopt(
+        long = "lenient",
+        help = "Skip malformed GFF3 or TSV lines instead of aborting, and report them at the end"
+    )]
+    pub lenient: bool,
+    #[structopt(
+        long = "sort",
+        possible_values(&SortBy::variants()),
+        help = "Sort output records. Records are written in input order by default"
+    )]
+    pub sort: Option<SortBy>,
 }
 
 arg_enum! {
@@ -44,10 +154,36 @@ arg_enum! {
 }
 
 arg_enum! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy)]
+    pub enum InputFormat {
+        GFF3,
+        TSV,
+        XML,
+        JSON
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy)]
     pub enum OutputFormat {
         ID,
         ALL,
-        TSV
+        TSV,
+        GO,
+        PATHWAY,
+        GAF,
+        FASTA,
+        GFF3,
+        JSON,
+        JSONL
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    pub enum SortBy {
+        ID,
+        LENGTH,
+        DOMAINS
     }
 }
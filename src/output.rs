@@ -0,0 +1,222 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Seek, SeekFrom, StdoutLock, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use interproscan_reader::compression::{Compression, Encoder};
+
+/// Where the output goes: buffered standard output, or a file compressed according to its
+/// extension.
+///
+/// A file is written under a temporary name next to it and renamed by
+/// [`Output::finish`], so a failed run never leaves a truncated file behind, nor
+/// replaces an existing one.
+pub enum Output {
+    Stdout(BufWriter<StdoutLock<'static>>),
+    File {
+        // boxed: the encoders of every compression format are large
+        writer: Option<Box<BufWriter<Encoder<File>>>>,
+        temp: PathBuf,
+        path: PathBuf,
+    },
+}
+
+impl Output {
+    /// Standard output when `path` is `None` or `-`.
+    pub fn create(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = match path {
+            Some(path) if path != Path::new("-") => path,
+            _ => return Ok(Self::Stdout(BufWriter::new(io::stdout().lock()))),
+        };
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file name", path.display()))?;
+        let temp = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            process::id()
+        ));
+
+        let file = File::create(&temp)?;
+        let encoder = match Encoder::new(Compression::from_extension(&path), file) {
+            Ok(encoder) => encoder,
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                return Err(err.into());
+            }
+        };
+        debug!("writing {:?} through {:?}", path, temp);
+
+        Ok(Self::File {
+            writer: Some(Box::new(BufWriter::new(encoder))),
+            temp,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Flush everything and move a file output into place.
+    pub fn finish(mut self) -> io::Result<()> {
+        match &mut self {
+            Self::Stdout(writer) => writer.flush(),
+            Self::File { writer, temp, path } => {
+                let writer = writer.take().expect("finished once");
+                let result = persist(*writer, temp, path);
+                if result.is_err() {
+                    let _ = fs::remove_file(temp);
+                }
+                result
+            }
+        }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Stdout(writer) => writer,
+            Self::File { writer, .. } => writer.as_mut().expect("not finished"),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        // the output was not finished: remove what was written of it
+        if let Self::File {
+            writer: Some(_),
+            temp,
+            ..
+        } = self
+        {
+            let _ = fs::remove_file(temp);
+        }
+    }
+}
+
+fn persist(writer: BufWriter<Encoder<File>>, temp: &Path, path: &Path) -> io::Result<()> {
+    let encoder = writer.into_inner().map_err(|err| err.into_error())?;
+    encoder.finish()?.sync_all()?;
+    fs::rename(temp, path)
+}
+
+/// Output set aside in a temporary file until the rest is written, such as the sequences
+/// that end GFF3 output. The file is removed when the spool is dropped.
+pub struct Spool {
+    writer: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl Spool {
+    pub fn create() -> io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            ".ir.{}.{}.spool",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        debug!("spooling to {:?}", path);
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            path,
+        })
+    }
+
+    /// Write everything spooled so far to `out`.
+    pub fn copy_to(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(file, out)?;
+        Ok(())
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether `err` comes from writing to a closed pipe, as in `ir ... | head`.
+pub fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+}
+
+#[cfg(test)]
+mod test_output {
+    use super::*;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn temp_file() {
+        let dir = std::env::temp_dir().join(format!("ir-{}-output", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut output = Output::create(Some(&dir.join("out.tsv"))).unwrap();
+        writeln!(output, "a").unwrap();
+        output.finish().unwrap();
+        assert_eq!(entries(&dir), ["out.tsv"]);
+
+        // an encoder that cannot be built leaves nothing behind
+        #[cfg(not(feature = "zstd"))]
+        {
+            assert!(Output::create(Some(&dir.join("out.zst"))).is_err());
+            assert_eq!(entries(&dir), ["out.tsv"]);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spool() {
+        let mut spool = Spool::create().unwrap();
+        let path = spool.path.clone();
+        writeln!(spool, ">a").unwrap();
+        writeln!(spool, "MSTN").unwrap();
+
+        let mut out = b"##FASTA\n".to_vec();
+        spool.copy_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "##FASTA\n>a\nMSTN\n");
+
+        drop(spool);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashSet;
+
+use super::pattern::Pattern;
+use crate::error::Error;
+use crate::records::{DomainRecord, GeneRecord};
+
+/// An ordered domain architecture such as `^PF00069 > PF00433` or `PF00400{4,} .. PF12894$`.
+///
+/// Domains are taken N- to C-terminal, by start then end. `a > b` requires `b` right after
+/// `a`, `a .. b` allows any domains in between. `{n}`, `{n,}`, `{,m}` and `{n,m}` repeat
+/// a domain in a row, and `^` and `$` anchor the pattern to the first and last domains.
+/// Names may be globs or `/regex/`, as in [`Expr`](super::Expr).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Architecture {
+    steps: Vec<Step>,
+    anchored_start: bool,
+    anchored_end: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    name: String,
+    pattern: Option<Pattern>,
+    min: usize,
+    max: usize,
+    /// Whether other domains may come before this step (`..`).
+    gap: bool,
+}
+
+impl Step {
+    fn is_match(&self, name: &str) -> bool {
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(name),
+            None => self.name == name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ArchToken {
+    Name(String),
+    /// `>`
+    Then,
+    /// `..`
+    Gap,
+    Repeat(usize, usize),
+    /// `^`
+    Start,
+    /// `$`
+    End,
+}
+
+fn lex(s: &str) -> Result<Vec<(ArchToken, usize)>, Error> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = vec![];
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '>' => ArchToken::Then,
+            '^' => ArchToken::Start,
+            '$' => ArchToken::End,
+            '.' if chars.get(i + 1) == Some(&'.') => {
+                i += 1;
+                ArchToken::Gap
+            }
+            '{' => {
+                let close = chars[i..]
+                    .iter()
+                    .position(|x| *x == '}')
+                    .ok_or_else(|| Error::expr(start, "unterminated repetition"))?;
+                let body: String = chars[i + 1..i + close].iter().collect();
+                i += close;
+                let (min, max) = parse_repeat(&body).ok_or_else(|| {
+                    Error::expr(start, format!("invalid repetition: {{{}}}", body))
+                })?;
+                ArchToken::Repeat(min, max)
+            }
+            _ => {
+                let mut name = String::new();
+                let mut in_regex = c == '/';
+                let mut escaped = false;
+                name.push(c);
+                i += 1;
+                while i < chars.len() {
+                    let c = chars[i];
+                    if in_regex {
+                        in_regex = escaped || c != '/';
+                        escaped = !escaped && c == '\\';
+                    } else if c.is_whitespace()
+                        || matches!(c, '>' | '{' | '^' | '$')
+                        || (c == '.' && chars.get(i + 1) == Some(&'.'))
+                    {
+                        break;
+                    }
+                    name.push(c);
+                    i += 1;
+                }
+                if in_regex {
+                    return Err(Error::expr(start, "unterminated regex"));
+                }
+                tokens.push((ArchToken::Name(name), start));
+                continue;
+            }
+        };
+
+        tokens.push((token, start));
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// `n`, `n,`, `,m` or `n,m`.
+fn parse_repeat(body: &str) -> Option<(usize, usize)> {
+    let body = body.trim();
+    let (min, max) = match body.split_once(',') {
+        Some((min, max)) => {
+            let (min, max) = (min.trim(), max.trim());
+            let min = if min.is_empty() { 0 } else { min.parse().ok()? };
+            let max = if max.is_empty() {
+                usize::MAX
+            } else {
+                max.parse().ok()?
+            };
+            (min, max)
+        }
+        None => {
+            let count = body.parse().ok()?;
+            (count, count)
+        }
+    };
+
+    (min <= max && max > 0).then_some((min, max))
+}
+
+impl Architecture {
+    pub fn from_string(s: &str) -> Result<Self, Error> {
+        let mut tokens = lex(s)?.into_iter().peekable();
+        let end = s.chars().count();
+
+        let anchored_start = tokens
+            .next_if(|(token, _)| *token == ArchToken::Start)
+            .is_some();
+        let mut anchored_end = false;
+        let mut steps: Vec<Step> = vec![];
+
+        while let Some((token, position)) = tokens.next() {
+            if anchored_end {
+                return Err(Error::expr(
+                    position,
+                    "expected end of architecture after $",
+                ));
+            }
+
+            match token {
+                ArchToken::Name(name) => {
+                    if !steps.is_empty() {
+                        return Err(Error::expr(position, "expected > or .. before domain"));
+                    }
+                    steps.push(Self::step(name, position, false, &mut tokens)?);
+                }
+                ArchToken::Then | ArchToken::Gap if !steps.is_empty() => {
+                    let gap = token == ArchToken::Gap;
+                    match tokens.next() {
+                        Some((ArchToken::Name(name), position)) => {
+                            steps.push(Self::step(name, position, gap, &mut tokens)?);
+                        }
+                        Some((_, position)) => {
+                            return Err(Error::expr(position, "expected a domain"))
+                        }
+                        None => return Err(Error::expr(end, "expected a domain")),
+                    }
+                }
+                ArchToken::End => anchored_end = true,
+                ArchToken::Then | ArchToken::Gap => {
+                    return Err(Error::expr(position, "expected a domain"))
+                }
+                ArchToken::Repeat(..) => {
+                    return Err(Error::expr(position, "repetition must follow a domain"))
+                }
+                ArchToken::Start => return Err(Error::expr(position, "^ must come first")),
+            }
+        }
+
+        Ok(Self {
+            steps,
+            anchored_start,
+            anchored_end,
+        })
+    }
+
+    fn step(
+        name: String,
+        position: usize,
+        gap: bool,
+        tokens: &mut std::iter::Peekable<std::vec::IntoIter<(ArchToken, usize)>>,
+    ) -> Result<Step, Error> {
+        let repeat = tokens.next_if(|(token, _)| matches!(token, ArchToken::Repeat(..)));
+        let (min, max) = match repeat {
+            Some((ArchToken::Repeat(min, max), _)) => (min, max),
+            _ => (1, 1),
+        };
+
+        Ok(Step {
+            pattern: Pattern::parse(&name, position)?,
+            name,
+            min,
+            max,
+            gap,
+        })
+    }
+
+    /// Match against the domains of `gene_record`.
+    pub fn matches(&self, gene_record: &GeneRecord) -> bool {
+        let mut domains: Vec<&DomainRecord> = gene_record.iter_domains().collect();
+        domains.sort_by_key(|domain| (domain.start, domain.end));
+        let names: Vec<&str> = domains
+            .iter()
+            .map(|domain| domain.domain_name.as_str())
+            .collect();
+
+        self.matches_names(&names)
+    }
+
+    /// Match against domain names already in N- to C-terminal order.
+    pub fn matches_names(&self, names: &[&str]) -> bool {
+        let mut failed = HashSet::new();
+        if self.anchored_start {
+            self.match_from(names, 0, 0, &mut failed)
+        } else {
+            (0..=names.len()).any(|start| self.match_from(names, 0, start, &mut failed))
+        }
+    }
+
+    /// Whether the steps from `step` on match `names` from `position` on. `failed` holds
+    /// the states already known not to match.
+    fn match_from(
+        &self,
+        names: &[&str],
+        step: usize,
+        position: usize,
+        failed: &mut HashSet<(usize, usize)>,
+    ) -> bool {
+        let current = match self.steps.get(step) {
+            Some(current) => current,
+            None => return !self.anchored_end || position == names.len(),
+        };
+        if failed.contains(&(step, position)) {
+            return false;
+        }
+
+        let last_start = if current.gap { names.len() } else { position };
+        for start in position..=last_start {
+            let copies = names[start..]
+                .iter()
+                .take(current.max)
+                .take_while(|name| current.is_match(name))
+                .count();
+            if copies < current.min {
+                continue;
+            }
+
+            for count in (current.min..=copies).rev() {
+                if self.match_from(names, step + 1, start + count, failed) {
+                    return true;
+                }
+            }
+        }
+
+        failed.insert((step, position));
+        false
+    }
+}
+
+#[cfg(test)]
+mod test_arch {
+    use super::*;
+
+    fn matches(arch: &str, names: &[&str]) -> bool {
+        Architecture::from_string(arch)
+            .unwrap()
+            .matches_names(names)
+    }
+
+    #[test]
+    fn order() {
+        let names = ["PF00069", "PF00433", "Coil"];
+
+        assert!(matches("PF00069 > PF00433", &names));
+        assert!(!matches("PF00433 > PF00069", &names));
+        assert!(!matches("PF00069 > Coil", &names));
+        assert!(matches("PF00069 .. Coil", &names));
+        assert!(matches("PF00069..Coil", &names));
+        assert!(matches("PF0006* > /^PF004\\d+$/", &names));
+    }
+
+    #[test]
+    fn anchors() {
+        let names = ["PF00069", "PF00433", "Coil"];
+
+        assert!(matches("^PF00069", &names));
+        assert!(!matches("^PF00433", &names));
+        assert!(matches("PF00433 > Coil$", &names));
+        assert!(!matches("PF00433$", &names));
+        assert!(matches("^PF00069 .. Coil $", &names));
+        assert!(matches("", &names));
+        assert!(matches("^$", &[]));
+        assert!(!matches("^$", &names));
+    }
+
+    #[test]
+    fn repeat() {
+        let names = [
+            "PF12894", "PF00400", "PF00400", "PF00400", "PF00400", "PF08662",
+        ];
+
+        assert!(matches("PF00400{4,}", &names));
+        assert!(!matches("PF00400{5,}", &names));
+        assert!(matches("PF12894 > PF00400{2,4} > PF08662", &names));
+        assert!(!matches("PF12894 > PF00400{,3} > PF08662", &names));
+        assert!(matches("^PF12894 > PF00400{3} .. PF08662$", &names));
+        assert!(matches("PF12894 > PF00400{0,} > PF00400{4}", &names));
+    }
+
+    #[test]
+    fn error_position() {
+        let position = |s: &str| match Architecture::from_string(s) {
+            Err(Error::Expr { position, .. }) => position,
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(position("a b"), 2);
+        assert_eq!(position("a > > b"), 4);
+        assert_eq!(position("a >"), 3);
+        assert_eq!(position("> a"), 0);
+        assert_eq!(position("a{x}"), 1);
+        assert_eq!(position("a{3,1}"), 1);
+        assert_eq!(position("a$ > b"), 3);
+        assert_eq!(position("a ^b"), 2);
+        assert_eq!(position("a > /b"), 4);
+        assert_eq!(position("a{2"), 1);
+    }
+}
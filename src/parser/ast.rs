@@ -1,197 +1,550 @@
-use super::lex::Token;
-use std::{collections::VecDeque, error::Error, fmt};
+use super::lex::{CompareOp, Token};
+use super::pattern::Pattern;
+use super::predicate::Predicate;
+use crate::error::Error;
+use crate::records::DomainRecord;
+use std::collections::VecDeque;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Node {
     Invert(Box<Node>),
-    And { lhs: Box<Node>, rhs: Box<Node> },
-    Or { lhs: Box<Node>, rhs: Box<Node> },
+    And {
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
+    Or {
+        lhs: Box<Node>,
+        rhs: Box<Node>,
+    },
     Name(String),
+    /// A glob or `/regex/` name, true when any tag matches.
+    Pattern(Pattern),
+    /// How many tags a [`Node::Name`] or [`Node::Pattern`] matches, such as `PF00400>=4`.
+    Count {
+        operand: Box<Node>,
+        comparison: Comparison,
+    },
+    /// A comparison on a field of one domain, such as `evalue<1e-10`.
+    Field(Predicate),
+    /// `any(...)` or `all(...)`: the operand is matched against each domain in turn.
+    Quantified {
+        quantifier: Quantifier,
+        operand: Box<Node>,
+        /// Character offset of `any` or `all` in the expression.
+        position: usize,
+    },
 }
 
-#[derive(Debug)]
-pub struct ParseError {
-    error: String,
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
+pub enum Quantifier {
+    /// True when one of the domains matches.
+    Any,
+    /// True when every domain matches, or there are none.
+    All,
 }
 
-impl ParseError {
-    pub fn new<S: ToString>(error: S) -> Self {
-        Self {
-            error: error.to_string(),
+/// The domains [`Node::Field`] and [`Node::Quantified`] are matched against.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scope<'a> {
+    /// Every domain of the protein.
+    pub domains: Option<&'a [DomainRecord]>,
+    /// The domain field predicates apply to.
+    pub domain: Option<&'a DomainRecord>,
+}
+
+/// The number of copies a [`Node::Count`] requires.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
+pub enum Comparison {
+    Eq(usize),
+    Ne(usize),
+    Lt(usize),
+    Le(usize),
+    Gt(usize),
+    Ge(usize),
+    /// `in min..max`, both included.
+    Between(usize, usize),
+}
+
+impl Comparison {
+    /// `None` when `value` is not a count.
+    pub fn parse(op: CompareOp, value: &str) -> Option<Self> {
+        let value = value.parse().ok()?;
+        let comparison = match op {
+            CompareOp::Eq => Self::Eq(value),
+            CompareOp::Ne => Self::Ne(value),
+            CompareOp::Lt => Self::Lt(value),
+            CompareOp::Le => Self::Le(value),
+            CompareOp::Gt => Self::Gt(value),
+            CompareOp::Ge => Self::Ge(value),
+        };
+
+        Some(comparison)
+    }
+
+    /// Parse the range of `in`: `2..4`, or `2..` and `..4` with one end open.
+    pub fn parse_range(value: &str) -> Option<Self> {
+        let (min, max) = value.split_once("..")?;
+        let min = if min.is_empty() { 0 } else { min.parse().ok()? };
+        let max = if max.is_empty() {
+            usize::MAX
+        } else {
+            max.parse().ok()?
+        };
+
+        Some(Self::Between(min, max))
+    }
+
+    pub fn holds(&self, count: usize) -> bool {
+        match *self {
+            Self::Eq(value) => count == value,
+            Self::Ne(value) => count != value,
+            Self::Lt(value) => count < value,
+            Self::Le(value) => count <= value,
+            Self::Gt(value) => count > value,
+            Self::Ge(value) => count >= value,
+            Self::Between(min, max) => (min..=max).contains(&count),
         }
     }
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "ParseError: {}", self.error)
+/// Binding power of a binary operator. `!` binds tighter than both.
+fn precedence(token: &Token) -> Option<u8> {
+    match token {
+        Token::Or => Some(1),
+        Token::And => Some(2),
+        _ => None,
     }
 }
 
-impl Error for ParseError {}
-
 impl Node {
-    pub fn munch_tokens(tokens: &mut VecDeque<Token>, depth: u16) -> Result<Self, Box<dyn Error>> {
+    /// Parse `tokens` into a tree: `!` binds tighter than `&`, which binds tighter than
+    /// `|`, and both binary operators are left-associative. `depth` bounds the nesting of
+    /// brackets and `!`. `end` is the length of the expression, reported as the position of
+    /// errors at the end of input.
+    ///
+    /// Parsing stops before an unmatched closing bracket.
+    pub fn munch_tokens(
+        tokens: &mut VecDeque<(Token, usize)>,
+        depth: u16,
+        end: usize,
+    ) -> Result<Self, Error> {
+        Self::binary(tokens, 1, depth, end)
+    }
+
+    /// Parse operands joined by operators of at least `min_precedence`.
+    fn binary(
+        tokens: &mut VecDeque<(Token, usize)>,
+        min_precedence: u8,
+        depth: u16,
+        end: usize,
+    ) -> Result<Self, Error> {
+        let mut lhs = Self::unary(tokens, depth, end)?;
+
+        while let Some((token, position)) = tokens.front() {
+            let precedence = match precedence(token) {
+                Some(precedence) => precedence,
+                None if *token == Token::CloseBracket => break,
+                None => return Err(Error::expr(*position, "expected & or | before this")),
+            };
+            if precedence < min_precedence {
+                break;
+            }
+
+            let (op, _) = tokens.pop_front().expect("an operator");
+            let rhs = Box::new(Self::binary(tokens, precedence + 1, depth, end)?);
+            let lhs_node = Box::new(lhs);
+            lhs = match op {
+                Token::And => Node::And { lhs: lhs_node, rhs },
+                _ => Node::Or { lhs: lhs_node, rhs },
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parse an operand, inverted by any number of `!`.
+    fn unary(tokens: &mut VecDeque<(Token, usize)>, depth: u16, end: usize) -> Result<Self, Error> {
+        let (token, position) = match tokens.pop_front() {
+            Some(next) => next,
+            None => return Err(Error::expr(end, "unexpected end of expression")),
+        };
         if depth == 0 {
-            let err: Box<dyn Error> = Box::new(ParseError::new("Expression too deep"));
-            return Err(err);
+            return Err(Error::expr(position, "Expression too deep"));
         }
 
-        let next = match tokens.front() {
-            Some(x) => x,
-            None => {
-                let err: Box<dyn Error> = Box::new(ParseError::new("unexpected end of expression"));
-                return Err(err);
+        match token {
+            Token::Invert => Ok(Node::Invert(Box::new(Self::unary(tokens, depth - 1, end)?))),
+            Token::OpenBracket => Self::bracketed(position, tokens, depth, end),
+            Token::Quantifier(quantifier) => match tokens.pop_front() {
+                Some((Token::OpenBracket, bracket_position)) => Ok(Self::Quantified {
+                    quantifier,
+                    operand: Box::new(Self::bracketed(bracket_position, tokens, depth, end)?),
+                    position,
+                }),
+                _ => Err(Error::expr(position, "expected an opening bracket")),
+            },
+            Token::Name(_) | Token::Quoted(_) | Token::Count(..) | Token::Field { .. } => {
+                Self::operand(&token, position)
             }
-        };
+            Token::CloseBracket => Err(Error::expr(position, "Unexpected closing bracket")),
+            Token::And | Token::Or => Err(Error::expr(position, "Unexpected binary operator")),
+            Token::Compare(_) | Token::In => Err(Error::expr(position, "Unexpected comparison")),
+        }
+    }
 
-        match next {
-            Token::CloseBracket => Err(Box::new(ParseError::new("Unexpected closing bracket"))),
-            Token::OpenBracket => {
-                let _ = tokens.pop_front();
-                let result = Self::munch_tokens(tokens, depth - 1)?;
-
-                if let Some(tk) = tokens.pop_front() {
-                    if tk != Token::CloseBracket {
-                        let err: Box<dyn Error> =
-                            Box::new(ParseError::new("expected closing bracket"));
-                        return Err(err);
-                    }
-                }
+    /// Parse the rest of a bracketed expression opened at `position`.
+    fn bracketed(
+        position: usize,
+        tokens: &mut VecDeque<(Token, usize)>,
+        depth: u16,
+        end: usize,
+    ) -> Result<Self, Error> {
+        let result = Self::munch_tokens(tokens, depth - 1, end)?;
 
-                return match tokens.front() {
-                    Some(Token::And) => {
-                        tokens.pop_front();
-                        let result = Node::And {
-                            lhs: Box::new(result),
-                            rhs: Box::new(Self::munch_tokens(tokens, depth - 1)?),
-                        };
-                        return Ok(result);
-                    }
-                    Some(Token::Or) => {
-                        let _ = tokens.pop_front();
-                        let result = Node::Or {
-                            lhs: Box::new(result),
-                            rhs: Box::new(Self::munch_tokens(tokens, depth - 1)?),
-                        };
-                        return Ok(result);
-                    }
-                    None | Some(Token::CloseBracket) => Ok(result),
-                    Some(_) => {
-                        let err: Box<dyn Error> =
-                            Box::new(ParseError::new("invald token after closing bracket"));
-                        return Err(err);
-                    }
-                };
-            }
-            Token::Invert => {
-                let _ = tokens.pop_front();
-
-                match tokens.front() {
-                    Some(Token::OpenBracket) => Ok(Node::Invert(Box::new(Self::munch_tokens(
-                        tokens,
-                        depth - 1,
-                    )?))),
-                    Some(Token::Name(text)) => {
-                        let inverted = Node::Invert(Box::new(Node::Name(text.clone())));
-                        match tokens.get(1) {
-                            Some(Token::And) | Some(Token::Or) => {
-                                // "!abc & xyz"
-                                // convert to unambiguous form and try again
-                                tokens.insert(0, Token::OpenBracket);
-                                tokens.insert(1, Token::Invert);
-                                tokens.insert(2, Token::OpenBracket);
-                                tokens.insert(4, Token::CloseBracket);
-                                tokens.insert(5, Token::CloseBracket);
-                                Self::munch_tokens(tokens, depth - 1)
-                            }
-                            None | Some(Token::CloseBracket) => {
-                                // "!abc"
-                                tokens.remove(0); // remove name
-                                Ok(inverted)
-                            }
-                            Some(_) => Err(Box::new(ParseError::new(
-                                "invalid token after inverted name",
-                            ))),
-                        }
+        match tokens.pop_front() {
+            Some((Token::CloseBracket, _)) => Ok(result),
+            _ => Err(Error::expr(position, "unclosed bracket")),
+        }
+    }
+
+    /// Build a [`Node::Name`] or [`Node::Pattern`] from a name token. The older `name$count`
+    /// form gives a [`Node::Count`].
+    fn name(text: &str, position: usize) -> Result<Self, Error> {
+        if text.starts_with('/') {
+            let pattern = Pattern::parse(text, position)?.expect("a regex");
+            return Ok(Self::Pattern(pattern));
+        }
+
+        match text.split_once('$') {
+            Some((name, count)) => {
+                let comparison = Comparison::parse(CompareOp::Eq, count).ok_or_else(|| {
+                    Error::expr(
+                        position + name.chars().count() + 1,
+                        format!("invalid count: {:?}", count),
+                    )
+                })?;
+                Self::count(name, comparison, position)
+            }
+            None => Ok(match Pattern::parse(text, position)? {
+                Some(pattern) => Self::Pattern(pattern),
+                None => Self::Name(text.to_string()),
+            }),
+        }
+    }
+
+    fn count(name: &str, comparison: Comparison, position: usize) -> Result<Self, Error> {
+        Ok(Self::Count {
+            operand: Box::new(Self::name(name, position)?),
+            comparison,
+        })
+    }
+
+    /// Build the node of a name or count token.
+    fn operand(token: &Token, position: usize) -> Result<Self, Error> {
+        match token {
+            Token::Name(text) => Self::name(text, position),
+            Token::Quoted(text) => Ok(Self::Name(text.clone())),
+            Token::Count(name, comparison) => Self::count(name, *comparison, position),
+            Token::Field {
+                field,
+                op,
+                value,
+                position: value_position,
+            } => Ok(Self::Field(Predicate::parse(
+                *field,
+                *op,
+                value,
+                position,
+                *value_position,
+            )?)),
+            _ => Err(Error::expr(position, "expected a name")),
+        }
+    }
+
+    /// Number of `tags` a [`Node::Name`] or [`Node::Pattern`] matches, 0 for other nodes.
+    fn count_matches(&self, tags: &[&str]) -> usize {
+        match self {
+            Self::Name(text) => tags.iter().filter(|x| **x == text).count(),
+            Self::Pattern(pattern) => tags.iter().filter(|x| pattern.is_match(x)).count(),
+            _ => 0,
+        }
+    }
+
+    /// Position of the first [`Node::Field`] outside of `any()` and `all()`, if any.
+    pub fn bare_field_position(&self) -> Option<usize> {
+        match self {
+            Self::Field(predicate) => Some(predicate.position),
+            Self::Invert(operand) | Self::Count { operand, .. } => operand.bare_field_position(),
+            Self::And { lhs, rhs } | Self::Or { lhs, rhs } => lhs
+                .bare_field_position()
+                .or_else(|| rhs.bare_field_position()),
+            Self::Name(_) | Self::Pattern(_) | Self::Quantified { .. } => None,
+        }
+    }
+
+    /// Gather the terms of a top-level `&` that contain fields outside of `any()` and
+    /// `all()` into a single `any()`, so that they apply to the same domain while the other
+    /// terms still apply to the whole protein. The `any()` takes the place, and the
+    /// position, of the first such term.
+    ///
+    /// A count next to such a field would only ever see one domain, so it is an error.
+    pub fn quantify_fields(self) -> Result<Self, Error> {
+        let mut terms = vec![];
+        self.into_terms(&mut terms);
+
+        let mut fields = vec![];
+        let mut first = None;
+        let mut others = vec![];
+        for term in terms {
+            match term.bare_field_position() {
+                Some(position) => {
+                    if term.has_bare_counts() {
+                        return Err(Error::expr(
+                            position,
+                            "counts apply to the whole protein: put fields next to counts in any()",
+                        ));
                     }
-                    Some(Token::Invert) => Err(Box::new(ParseError::new(
-                        "Can't double invert, that would be no mean",
-                    ))),
-                    Some(_) => Err(Box::new(ParseError::new("expected expression"))),
-                    None => Err(Box::new(ParseError::new(
-                        "Expected token to invert, got EOF",
-                    ))),
+                    first.get_or_insert((others.len(), position));
+                    fields.push(term);
                 }
+                None => others.push(term),
             }
-            Token::Name(text) => match tokens.get(1) {
-                Some(Token::And) | Some(Token::Or) => {
-                    add_bracket(tokens);
-                    Self::munch_tokens(tokens, depth - 1)
-                }
-                Some(Token::CloseBracket) | None => {
-                    let text = text.clone();
-                    let _ = tokens.pop_front();
-                    Ok(Node::Name(text))
-                }
-                Some(_) => Err(Box::new(ParseError::new("Name followed by invalid token"))),
-            },
-            Token::And | Token::Or => Err(Box::new(ParseError::new("Unexpected binary operator"))),
+        }
+
+        let (index, position) = match first {
+            Some(first) => first,
+            None => return Ok(Self::and_all(others)),
+        };
+        let quantified = Self::Quantified {
+            quantifier: Quantifier::Any,
+            operand: Box::new(Self::and_all(fields)),
+            position,
+        };
+        others.insert(index, quantified);
+
+        Ok(Self::and_all(others))
+    }
+
+    /// The terms of a chain of `&`, in order.
+    fn into_terms(self, terms: &mut Vec<Self>) {
+        match self {
+            Self::And { lhs, rhs } => {
+                lhs.into_terms(terms);
+                rhs.into_terms(terms);
+            }
+            term => terms.push(term),
         }
     }
 
-    pub fn matches(&self, tags: &[&str]) -> Result<bool, Box<dyn Error>> {
+    /// Join `terms` with left-associative `&`, as the parser does. `terms` is not empty.
+    fn and_all(terms: Vec<Self>) -> Self {
+        terms
+            .into_iter()
+            .reduce(|lhs, rhs| Self::And {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            })
+            .expect("at least one term")
+    }
+
+    /// Whether a [`Node::Count`] appears outside of `any()` and `all()`.
+    fn has_bare_counts(&self) -> bool {
+        match self {
+            Self::Count { .. } => true,
+            Self::Invert(operand) => operand.has_bare_counts(),
+            Self::And { lhs, rhs } | Self::Or { lhs, rhs } => {
+                lhs.has_bare_counts() || rhs.has_bare_counts()
+            }
+            Self::Name(_) | Self::Pattern(_) | Self::Field(_) | Self::Quantified { .. } => false,
+        }
+    }
+
+    /// Position of the first [`Node::Field`] or [`Node::Quantified`], which need domains.
+    pub fn domain_position(&self) -> Option<usize> {
+        match self {
+            Self::Field(predicate) => Some(predicate.position),
+            Self::Quantified { position, .. } => Some(*position),
+            Self::Invert(operand) | Self::Count { operand, .. } => operand.domain_position(),
+            Self::And { lhs, rhs } | Self::Or { lhs, rhs } => {
+                lhs.domain_position().or_else(|| rhs.domain_position())
+            }
+            Self::Name(_) | Self::Pattern(_) => None,
+        }
+    }
+
+    pub fn matches(&self, tags: &[&str]) -> Result<bool, Error> {
+        self.matches_in(tags, Scope::default())
+    }
+
+    /// Like [`Node::matches`], with the domains fields and quantifiers need.
+    pub fn matches_in(&self, tags: &[&str], scope: Scope) -> Result<bool, Error> {
         let result = match self {
-            Self::Invert(inverted) => !inverted.matches(tags)?,
-            Self::Name(text) => {
-                // counting numbers of elements
-                let splitted: Vec<&str> = text.split('$').collect();
-                match splitted.len() {
-                    1 => tags.contains(&&**text),
-                    2 => {
-                        let count = splitted[1].parse::<usize>()?;
-                        count == tags.iter().filter(|x| x == &&splitted[0]).count()
-                    }
-                    _ => return Err(Box::new(ParseError::new("unexpected text format"))),
+            Self::Invert(inverted) => !inverted.matches_in(tags, scope)?,
+            Self::Name(text) => tags.contains(&text.as_str()),
+            Self::Count {
+                operand,
+                comparison,
+            } => comparison.holds(operand.count_matches(tags)),
+            Self::Pattern(pattern) => tags.iter().any(|tag| pattern.is_match(tag)),
+            Self::Field(predicate) => match scope.domain {
+                Some(domain) => predicate.holds(domain),
+                None => {
+                    return Err(Error::expr(
+                        predicate.position,
+                        "fields, any() and all() only apply to domain expressions",
+                    ))
                 }
+            },
+            Self::Quantified {
+                quantifier,
+                operand,
+                position,
+            } => operand.quantify(*quantifier, *position, tags, scope)?,
+            Self::And { lhs, rhs } => {
+                lhs.matches_in(tags, scope)? && rhs.matches_in(tags, scope)?
             }
-            Self::And { lhs, rhs } => lhs.matches(tags)? && rhs.matches(tags)?,
-            Self::Or { lhs, rhs } => lhs.matches(tags)? || rhs.matches(tags)?,
+            Self::Or { lhs, rhs } => lhs.matches_in(tags, scope)? || rhs.matches_in(tags, scope)?,
         };
 
         Ok(result)
     }
-}
 
-fn add_bracket(tokens: &mut VecDeque<Token>) {
-    let elem = tokens.pop_front().unwrap();
-    tokens.push_front(Token::CloseBracket);
-    tokens.push_front(elem);
-    tokens.push_front(Token::OpenBracket);
+    /// Match each domain of `scope` in turn, names then standing for the name of that
+    /// domain. A scope of a single domain is matched as is.
+    fn quantify(
+        &self,
+        quantifier: Quantifier,
+        position: usize,
+        tags: &[&str],
+        scope: Scope,
+    ) -> Result<bool, Error> {
+        let domains = match (scope.domains, scope.domain) {
+            (Some(domains), _) => domains,
+            (None, Some(_)) => return self.matches_in(tags, scope),
+            (None, None) => {
+                return Err(Error::expr(
+                    position,
+                    "fields, any() and all() only apply to domain expressions",
+                ))
+            }
+        };
+
+        let all = quantifier == Quantifier::All;
+        for domain in domains {
+            let scope = Scope {
+                domains: scope.domains,
+                domain: Some(domain),
+            };
+            if self.matches_in(&[domain.domain_name.as_str()], scope)? != all {
+                return Ok(!all);
+            }
+        }
+
+        Ok(all)
+    }
 }
 
 #[cfg(test)]
 mod test_ast {
+    use super::super::lex::lex_spanned;
     use super::*;
 
+    fn parse(s: &str) -> Result<Node, Error> {
+        let mut tokens = VecDeque::from(lex_spanned(s)?);
+        Node::munch_tokens(&mut tokens, 20, s.chars().count())
+    }
+
+    fn name(s: &str) -> Box<Node> {
+        Box::new(Node::Name(s.to_string()))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("a | b & c").unwrap(),
+            Node::Or {
+                lhs: name("a"),
+                rhs: Box::new(Node::And {
+                    lhs: name("b"),
+                    rhs: name("c"),
+                }),
+            }
+        );
+        assert_eq!(
+            parse("a & b | c").unwrap(),
+            Node::Or {
+                lhs: Box::new(Node::And {
+                    lhs: name("a"),
+                    rhs: name("b"),
+                }),
+                rhs: name("c"),
+            }
+        );
+        assert_eq!(
+            parse("!a & b").unwrap(),
+            Node::And {
+                lhs: Box::new(Node::Invert(name("a"))),
+                rhs: name("b"),
+            }
+        );
+        assert_eq!(
+            parse("!(a) | b").unwrap(),
+            Node::Or {
+                lhs: Box::new(Node::Invert(name("a"))),
+                rhs: name("b"),
+            }
+        );
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(
+            parse("a | b | c").unwrap(),
+            Node::Or {
+                lhs: Box::new(Node::Or {
+                    lhs: name("a"),
+                    rhs: name("b"),
+                }),
+                rhs: name("c"),
+            }
+        );
+        assert_eq!(
+            parse("a & (b | c) & d").unwrap(),
+            Node::And {
+                lhs: Box::new(Node::And {
+                    lhs: name("a"),
+                    rhs: Box::new(Node::Or {
+                        lhs: name("b"),
+                        rhs: name("c"),
+                    }),
+                }),
+                rhs: name("d"),
+            }
+        );
+    }
+
+    #[test]
+    fn double_inversion() {
+        assert_eq!(
+            parse("!!a").unwrap(),
+            Node::Invert(Box::new(Node::Invert(name("a"))))
+        );
+        assert_eq!(
+            parse("!!(a) & any(b)").unwrap(),
+            Node::And {
+                lhs: Box::new(Node::Invert(Box::new(Node::Invert(name("a"))))),
+                rhs: Box::new(Node::Quantified {
+                    quantifier: Quantifier::Any,
+                    operand: name("b"),
+                    position: 8,
+                }),
+            }
+        );
+    }
+
     #[test]
-    fn test_addbracket() {
-        let mut vq3: VecDeque<Token> = VecDeque::from(vec![
-            Token::Name("a".to_string()),
-            Token::And,
-            Token::Name("b".to_string()),
-        ]);
-
-        add_bracket(&mut vq3);
-
-        let excpected = VecDeque::from(vec![
-            Token::OpenBracket,
-            Token::Name("a".to_string()),
-            Token::CloseBracket,
-            Token::And,
-            Token::Name("b".to_string()),
-        ]);
-
-        assert_eq!(vq3, excpected);
+    fn too_deep() {
+        let s = format!("{}a", "!".repeat(25));
+        assert!(matches!(parse(&s), Err(Error::Expr { position: 20, .. })));
     }
 }
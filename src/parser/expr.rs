@@ -1,9 +1,10 @@
-use std::{collections::VecDeque, error::Error};
+use std::collections::VecDeque;
 
-use crate::records::GeneRecord;
+use crate::error::Error;
+use crate::records::{DomainRecord, GeneRecord};
 
-use super::ast::{Node, ParseError};
-use super::lex::{lex, Token};
+use super::ast::{Node, Scope};
+use super::lex::{lex_spanned, Token};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ExprData {
@@ -17,36 +18,95 @@ pub struct Expr(ExprData);
 pub const MAX_RECURSION: u16 = 20;
 
 impl Expr {
-    pub fn from_string(s: &str) -> Result<Self, Box<dyn Error>> {
+    pub fn from_string(s: &str) -> Result<Self, Error> {
         // lex and convert to a deque
-        let mut tokens: VecDeque<Token> = VecDeque::from(lex(s)?);
+        let mut tokens: VecDeque<(Token, usize)> = VecDeque::from(lex_spanned(s)?);
         if tokens.is_empty() {
             // no tokens
             return Ok(Self(ExprData::Empty));
         }
 
-        let ast = Node::munch_tokens(&mut tokens, MAX_RECURSION)?;
-        if !tokens.is_empty() {
-            return Err(Box::new(ParseError::new(
-                "expected EOF, found extra tokens",
-            )));
+        let mut ast = Node::munch_tokens(&mut tokens, MAX_RECURSION, s.chars().count())?;
+        // parsing only stops early at a closing bracket
+        if let Some((_, position)) = tokens.front() {
+            return Err(Error::expr(*position, "Unexpected closing bracket"));
+        }
+
+        // "name=PF00069 & start<300" is about one domain
+        if ast.bare_field_position().is_some() {
+            ast = ast.quantify_fields()?;
         }
 
         Ok(Self(ExprData::HasNodes(ast)))
     }
 
-    pub fn matches(&self, tags: &[&str]) -> Result<bool, Box<dyn Error>> {
+    /// Reject fields, `any()` and `all()` in an expression that is only matched against
+    /// tags, such as GO terms, where they would fail on every record.
+    pub fn without_fields(self) -> Result<Self, Error> {
+        if let ExprData::HasNodes(node) = &self.0 {
+            if let Some(position) = node.domain_position() {
+                return Err(Error::expr(
+                    position,
+                    "fields, any() and all() only apply to domain expressions",
+                ));
+            }
+        }
+
+        Ok(self)
+    }
+
+    pub fn matches(&self, tags: &[&str]) -> Result<bool, Error> {
         match &self.0 {
             ExprData::Empty => Ok(true),
             ExprData::HasNodes(node) => node.matches(tags),
         }
     }
 
-    pub fn matches_domains(&self, gene_record: &GeneRecord) -> Result<bool, Box<dyn Error>> {
+    fn matches_in(&self, tags: &[&str], scope: Scope) -> Result<bool, Error> {
+        match &self.0 {
+            ExprData::Empty => Ok(true),
+            ExprData::HasNodes(node) => node.matches_in(tags, scope),
+        }
+    }
+
+    /// Match against the domain names of `gene_record`. Fields such as `evalue<1e-10`
+    /// apply to its domains.
+    pub fn matches_domains(&self, gene_record: &GeneRecord) -> Result<bool, Error> {
         let tags: Vec<&str> = gene_record
             .iter_domains()
             .map(|domain| domain.domain_name.as_str())
             .collect();
+        let scope = Scope {
+            domains: Some(gene_record.iter_domains().as_slice()),
+            domain: None,
+        };
+
+        self.matches_in(&tags, scope)
+    }
+
+    /// Match against the name and InterPro accessions of a single domain.
+    pub fn matches_domain(&self, domain: &DomainRecord) -> Result<bool, Error> {
+        let mut tags = domain.interpro_accessions();
+        tags.push(&domain.domain_name);
+        let scope = Scope {
+            domains: None,
+            domain: Some(domain),
+        };
+
+        self.matches_in(&tags, scope)
+    }
+
+    /// Match against the GO terms of `gene_record`.
+    pub fn matches_go(&self, gene_record: &GeneRecord) -> Result<bool, Error> {
+        self.matches(&gene_record.go_terms())
+    }
+
+    /// Match against the InterPro accessions of every domain of `gene_record`.
+    pub fn matches_interpro(&self, gene_record: &GeneRecord) -> Result<bool, Error> {
+        let tags: Vec<&str> = gene_record
+            .iter_domains()
+            .flat_map(|domain| domain.interpro_accessions())
+            .collect();
 
         self.matches(&tags)
     }
@@ -54,6 +114,7 @@ impl Expr {
 
 #[cfg(test)]
 mod test_expr {
+    use super::super::ast::{Comparison, Quantifier};
     use super::*;
     #[test]
     fn or_alias() {
@@ -251,4 +312,198 @@ mod test_expr {
         assert!(expr.matches(&["d", "e", "c"]).unwrap());
         assert!(!expr.matches(&["d"]).unwrap());
     }
+
+    #[test]
+    fn patterns() {
+        let expr = Expr::from_string("PTHR10566* & !/^PF0\\d{4}$/").unwrap();
+        assert!(expr.matches(&["PTHR10566:SF3", "PF12738"]).unwrap());
+        assert!(!expr.matches(&["PTHR10566:SF3", "PF00533"]).unwrap());
+        assert!(!expr.matches(&["PTHR10567"]).unwrap());
+
+        let expr = Expr::from_string("/PF00533|PF12738/ , G3DSA:3.40.50.*").unwrap();
+        assert!(expr.matches(&["PF12738"]).unwrap());
+        assert!(expr.matches(&["G3DSA:3.40.50.10190"]).unwrap());
+        assert!(!expr.matches(&["G3DSA:1.10.8.10"]).unwrap());
+
+        // quoted names are taken literally
+        assert!(!Expr::from_string("ID[1]")
+            .unwrap()
+            .matches(&["ID[1]"])
+            .unwrap());
+        let expr = Expr::from_string(r#""ID[1]" | "PTHR10566*""#).unwrap();
+        assert!(expr.matches(&["ID[1]"]).unwrap());
+        assert!(expr.matches(&["PTHR10566*"]).unwrap());
+        assert!(!expr.matches(&["ID1", "PTHR10566:SF3"]).unwrap());
+    }
+
+    #[test]
+    fn counts() {
+        let tags = ["PF00400", "PF00400", "PF00400", "PF00400", "PF00069"];
+
+        assert!(Expr::from_string("PF00400>=4")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+        assert!(!Expr::from_string("PF00400 > 4")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+        assert!(Expr::from_string("PF00069<2 & PF00400$4")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+        assert!(Expr::from_string("PF00400 in 2..4")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+        assert!(Expr::from_string("!(PF00400 in 5..)")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+        assert!(Expr::from_string("PF12738 == 0 & PF00069!=2")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+        assert!(Expr::from_string("PF0040*=4")
+            .unwrap()
+            .matches(&tags)
+            .unwrap());
+
+        assert_eq!(
+            Expr::from_string("!a<=2").unwrap().0,
+            ExprData::HasNodes(Node::Invert(Box::new(Node::Count {
+                operand: Box::new(Node::Name("a".to_string())),
+                comparison: Comparison::Le(2),
+            })))
+        );
+    }
+
+    #[test]
+    fn fields() {
+        let mut record = GeneRecord::new("prot1".to_string(), 1, 500);
+        record.push_domain(
+            DomainRecord::new("Pfam", 20, 280, "PF00069", "Protein kinase domain")
+                .with_score(Some(1e-40)),
+        );
+        record.push_domain(
+            DomainRecord::new("Pfam", 350, 380, "PF00400", "WD domain").with_score(Some(1e-3)),
+        );
+        record.push_domain(DomainRecord::new("Coils", 400, 420, "Coil", ""));
+
+        let matches = |s: &str| {
+            Expr::from_string(s)
+                .unwrap()
+                .matches_domains(&record)
+                .unwrap()
+        };
+
+        assert!(matches(
+            "name=PF00069 & source=Pfam & evalue<1e-10 & start<300"
+        ));
+        assert!(!matches("name=PF00400 & evalue<1e-10"));
+        assert!(matches("PF00400 & any(name=PF00069 & desc=*kinase*)"));
+        assert!(!matches("all(source=Pfam)"));
+        assert!(matches("all(source=Pfam | Coil)"));
+        assert!(matches("!all(evalue<1e-10) & any(end>=400)"));
+
+        assert!(matches!(
+            Expr::from_string("start<300").unwrap().0,
+            ExprData::HasNodes(Node::Quantified {
+                quantifier: Quantifier::Any,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Expr::from_string("a & any(start<300)")
+                .unwrap()
+                .matches(&["a"]),
+            Err(Error::Expr { position: 4, .. })
+        ));
+        assert!(matches!(
+            Expr::from_string("GO:0005515 | !all(PF00069)").and_then(Expr::without_fields),
+            Err(Error::Expr { position: 14, .. })
+        ));
+        assert!(Expr::from_string("GO:0005515 | !GO:0016301")
+            .and_then(Expr::without_fields)
+            .is_ok());
+        // the implicit any() is reported at the first field
+        assert!(matches!(
+            Expr::from_string("a & start<5").unwrap().matches(&["a"]),
+            Err(Error::Expr { position: 4, .. })
+        ));
+
+        let domain = record.iter_domains().next().unwrap();
+        assert!(Expr::from_string("PF00069 & evalue<1e-10")
+            .unwrap()
+            .matches_domain(domain)
+            .unwrap());
+    }
+
+    #[test]
+    fn fields_with_counts() {
+        let mut record = GeneRecord::new("prot1".to_string(), 1, 1200);
+        record.push_domain(
+            DomainRecord::new("Pfam", 100, 140, "PF00400", "WD domain").with_score(Some(1e-8)),
+        );
+        record.push_domain(
+            DomainRecord::new("Pfam", 150, 190, "PF00400", "WD domain").with_score(Some(1e-6)),
+        );
+        record.push_domain(
+            DomainRecord::new("SMART", 300, 500, "SM00220", "kinase").with_score(Some(1e-40)),
+        );
+
+        let matches = |s: &str| {
+            Expr::from_string(s)
+                .unwrap()
+                .matches_domains(&record)
+                .unwrap()
+        };
+
+        // counts and names next to fields still apply to the whole protein
+        assert!(matches("PF00400>=2 & start<1000"));
+        assert!(matches("PF00400$2 & source=Pfam"));
+        assert!(!matches("PF00400>=3 & start<1000"));
+        assert!(matches("!PF00069 & evalue<1e-10"));
+        assert!(!matches("!SM00220 & evalue<1e-10"));
+        // while the fields apply to the same domain
+        assert!(matches("PF00400>=2 & source=SMART & evalue<1e-10"));
+        assert!(!matches("PF00400>=2 & source=Pfam & evalue<1e-10"));
+
+        assert!(matches!(
+            Expr::from_string("PF00400>=2 & source=Pfam").unwrap().0,
+            ExprData::HasNodes(Node::And { ref rhs, .. })
+                if matches!(**rhs, Node::Quantified { position: 13, .. })
+        ));
+        // a count in the same term as a field would only see one domain
+        assert!(matches!(
+            Expr::from_string("a & (PF00400>=2 | start<5)"),
+            Err(Error::Expr { position: 18, .. })
+        ));
+    }
+
+    #[test]
+    fn error_position() {
+        let position = |s: &str| match Expr::from_string(s) {
+            Err(Error::Expr { position, .. }) => position,
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(position("a & "), 4);
+        assert_eq!(position("a & )"), 4);
+        assert_eq!(position("a b"), 2);
+        assert_eq!(position("(a & b) c"), 8);
+        assert_eq!(position("a & b$x"), 6);
+        assert_eq!(position("a & /(/"), 4);
+        assert_eq!(position("a & PF[0"), 6);
+        assert_eq!(position("a >= x"), 5);
+        assert_eq!(position("a in 2"), 5);
+        assert_eq!(position("a & >= 2"), 4);
+        assert_eq!(position("a >="), 4);
+        assert_eq!(position("evalue < x"), 9);
+        assert_eq!(position("a | source < Pfam"), 4);
+        assert_eq!(position("a & (b | c"), 4);
+        assert_eq!(position("(a | b)) & c"), 7);
+        assert_eq!(position("a & !"), 5);
+        assert_eq!(position("a | b !c"), 6);
+    }
 }
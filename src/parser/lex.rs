@@ -1,4 +1,6 @@
-use std::error::Error;
+use super::ast::{Comparison, Quantifier};
+use super::predicate::Field;
+use crate::error::Error;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq)]
 pub enum Token {
@@ -8,6 +10,47 @@ pub enum Token {
     And,
     Or,
     Name(String),
+    /// A `"quoted"` name, taken literally: never a pattern, a count or a field.
+    Quoted(String),
+    /// `=`, `!=`, `<`, `<=`, `>` or `>=`.
+    Compare(CompareOp),
+    /// The `in` of `PF12738 in 2..4`.
+    In,
+    /// A name with a comparison of its count, such as `PF00400>=4`.
+    Count(String, Comparison),
+    /// A comparison on a domain field, such as `evalue<1e-10`. `position` is that of
+    /// `value`.
+    Field {
+        field: Field,
+        op: CompareOp,
+        value: String,
+        position: usize,
+    },
+    /// The `any` or `all` of `any(...)`, followed by the opening bracket.
+    Quantifier(Quantifier),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    pub fn holds<T: PartialOrd>(&self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
 }
 
 impl Token {
@@ -27,27 +70,105 @@ impl Token {
 enum ParseState {
     Ready,
     InName,
+    /// Inside `/.../`, where operators are part of the regex. `escaped` follows a `\`.
+    InRegex {
+        escaped: bool,
+    },
+    /// Inside `"..."`, where everything is part of the name. `escaped` follows a `\`.
+    InQuote {
+        escaped: bool,
+    },
 }
 
-pub fn lex(s: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+/// A comparison operator starting with `c`, and whether it takes the following `=` too.
+fn compare_op(c: char, next: Option<char>) -> Option<(CompareOp, bool)> {
+    let op = match (c, next) {
+        ('=', Some('=')) => (CompareOp::Eq, true),
+        ('=', _) => (CompareOp::Eq, false),
+        ('!', Some('=')) => (CompareOp::Ne, true),
+        ('<', Some('=')) => (CompareOp::Le, true),
+        ('<', _) => (CompareOp::Lt, false),
+        ('>', Some('=')) => (CompareOp::Ge, true),
+        ('>', _) => (CompareOp::Gt, false),
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+fn name_token(name: String) -> Token {
+    if name == "in" {
+        Token::In
+    } else {
+        Token::Name(name)
+    }
+}
+
+pub fn lex(s: &str) -> Result<Vec<Token>, Error> {
+    Ok(lex_spanned(s)?
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect())
+}
+
+/// Like [`lex`], but every token comes with the character offset it starts at.
+pub fn lex_spanned(s: &str) -> Result<Vec<(Token, usize)>, Error> {
     let mut state = ParseState::Ready;
     let mut tokens = vec![];
 
     let mut cur_name = String::new();
+    let mut name_start = 0;
 
-    for c in s.chars() {
-        let op_token = Token::op_from_char(c);
+    let mut chars = s.chars().enumerate().peekable();
+    while let Some((position, c)) = chars.next() {
+        let op_token = match compare_op(c, chars.peek().map(|(_, next)| *next)) {
+            Some(_)
+                if matches!(
+                    state,
+                    ParseState::InRegex { .. } | ParseState::InQuote { .. }
+                ) =>
+            {
+                None
+            }
+            Some((op, two_chars)) => {
+                if two_chars {
+                    chars.next();
+                }
+                Some(Token::Compare(op))
+            }
+            None => Token::op_from_char(c),
+        };
         match state {
+            ParseState::InRegex { escaped } => {
+                cur_name.push(c);
+                state = match c {
+                    '/' if !escaped => ParseState::InName,
+                    '\\' if !escaped => ParseState::InRegex { escaped: true },
+                    _ => ParseState::InRegex { escaped: false },
+                };
+            }
+            ParseState::InQuote { escaped: true } => {
+                cur_name.push(c);
+                state = ParseState::InQuote { escaped: false };
+            }
+            ParseState::InQuote { escaped: false } => match c {
+                '\\' => state = ParseState::InQuote { escaped: true },
+                '"' => {
+                    tokens.push((Token::Quoted(std::mem::take(&mut cur_name)), name_start));
+                    state = ParseState::Ready;
+                }
+                c => cur_name.push(c),
+            },
             ParseState::InName => {
                 if let Some(op) = op_token {
-                    tokens.push(Token::Name(cur_name.to_owned()));
+                    tokens.push((name_token(cur_name.to_owned()), name_start));
 
-                    tokens.push(op);
+                    tokens.push((op, position));
 
                     state = ParseState::Ready;
                     cur_name = String::new();
                 } else if c.is_whitespace() {
-                    tokens.push(Token::Name(cur_name.to_owned()));
+                    tokens.push((name_token(cur_name.to_owned()), name_start));
                     state = ParseState::Ready;
                     cur_name = String::new();
                 } else {
@@ -56,20 +177,111 @@ pub fn lex(s: &str) -> Result<Vec<Token>, Box<dyn Error>> {
             }
             ParseState::Ready => {
                 if let Some(op) = op_token {
-                    tokens.push(op);
+                    tokens.push((op, position));
+                } else if c == '/' {
+                    cur_name.push(c);
+                    name_start = position;
+                    state = ParseState::InRegex { escaped: false }
+                } else if c == '"' {
+                    name_start = position;
+                    state = ParseState::InQuote { escaped: false }
                 } else if !c.is_whitespace() {
                     cur_name.push(c);
+                    name_start = position;
                     state = ParseState::InName
                 }
             }
         }
     }
 
+    if matches!(state, ParseState::InRegex { .. }) {
+        return Err(Error::expr(name_start, "unterminated regex"));
+    }
+    if matches!(state, ParseState::InQuote { .. }) {
+        return Err(Error::expr(name_start, "unterminated quote"));
+    }
+
     if !cur_name.is_empty() {
-        tokens.push(Token::Name(cur_name.to_owned()));
+        tokens.push((name_token(cur_name.to_owned()), name_start));
     }
 
-    Ok(tokens)
+    fold(tokens, s.chars().count())
+}
+
+/// Merge `name <op> count` and `name in range` into a single [`Token::Count`], or a
+/// [`Token::Field`] when the name is a domain field, and `any(` or `all(` into a
+/// [`Token::Quantifier`]. `end` is the length of the expression.
+fn fold(tokens: Vec<(Token, usize)>, end: usize) -> Result<Vec<(Token, usize)>, Error> {
+    let mut folded: Vec<(Token, usize)> = Vec::with_capacity(tokens.len());
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some((token, position)) = tokens.next() {
+        let op = match token {
+            Token::Compare(op) => Some(op),
+            Token::In => None,
+            Token::OpenBracket => {
+                let quantifier = match folded.last() {
+                    Some((Token::Name(name), _)) if name == "any" => Some(Quantifier::Any),
+                    Some((Token::Name(name), _)) if name == "all" => Some(Quantifier::All),
+                    _ => None,
+                };
+                if let Some(quantifier) = quantifier {
+                    let (_, name_position) = folded.pop().expect("a name");
+                    folded.push((Token::Quantifier(quantifier), name_position));
+                }
+                folded.push((token, position));
+                continue;
+            }
+            token => {
+                folded.push((token, position));
+                continue;
+            }
+        };
+
+        let name = match folded.pop() {
+            Some((Token::Name(name), name_position)) => (name, name_position),
+            _ => return Err(Error::expr(position, "expected a name before comparison")),
+        };
+        let (value, value_position) = match tokens.next() {
+            Some((Token::Name(value), value_position)) => (value, value_position),
+            Some((_, value_position)) => {
+                return Err(Error::expr(value_position, "expected a count"))
+            }
+            None => return Err(Error::expr(end, "expected a count")),
+        };
+
+        if let Some(field) = Field::from_name(&name.0) {
+            let op = op.ok_or_else(|| {
+                Error::expr(position, format!("`in` does not apply to {}", name.0))
+            })?;
+            let token = Token::Field {
+                field,
+                op,
+                value,
+                position: value_position,
+            };
+            folded.push((token, name.1));
+            continue;
+        }
+
+        let comparison = match op {
+            Some(op) => Comparison::parse(op, &value),
+            None => Comparison::parse_range(&value),
+        }
+        .ok_or_else(|| {
+            Error::expr(
+                value_position,
+                format!(
+                    "invalid count: {:?}; quote names containing =, < or >, as in \"a=b\"",
+                    value
+                ),
+            )
+        })?;
+
+        folded.push((Token::Count(name.0, comparison), name.1));
+    }
+
+    Ok(folded)
 }
 
 #[cfg(test)]
@@ -128,4 +340,112 @@ mod test_lex {
             tokens
         )
     }
+
+    #[test]
+    fn test_regex() {
+        let tokens = lex(r"/^(PF|SM)\d+$/ & !/a\/b/").unwrap();
+        assert_eq!(
+            vec![
+                Token::Name(r"/^(PF|SM)\d+$/".to_string()),
+                Token::And,
+                Token::Invert,
+                Token::Name(r"/a\/b/".to_string()),
+            ],
+            tokens
+        );
+
+        assert!(matches!(
+            lex("a | /b&c"),
+            Err(Error::Expr { position: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_quoted() {
+        let tokens = lex_spanned(r#"!"ID[1]" | "a=b & \"c\"" | """#).unwrap();
+        assert_eq!(
+            vec![
+                (Token::Invert, 0),
+                (Token::Quoted("ID[1]".to_string()), 1),
+                (Token::Or, 9),
+                (Token::Quoted(r#"a=b & "c""#.to_string()), 11),
+                (Token::Or, 25),
+                (Token::Quoted(String::new()), 27),
+            ],
+            tokens
+        );
+
+        assert!(matches!(
+            lex(r#"a | "b"#),
+            Err(Error::Expr { position: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_count() {
+        let tokens = lex_spanned("PF00400>=4 & PF00069 in 2..").unwrap();
+        assert_eq!(
+            vec![
+                (Token::Count("PF00400".to_string(), Comparison::Ge(4)), 0),
+                (Token::And, 11),
+                (
+                    Token::Count("PF00069".to_string(), Comparison::Between(2, usize::MAX)),
+                    13
+                ),
+            ],
+            tokens
+        );
+
+        assert!(matches!(lex("a < b"), Err(Error::Expr { position: 4, .. })));
+        // names containing comparisons are quoted
+        assert!(matches!(lex("a=b"), Err(Error::Expr { position: 2, .. })));
+        assert_eq!(
+            lex(r#""a=b""#).unwrap(),
+            vec![Token::Quoted("a=b".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_field() {
+        let tokens = lex_spanned("any(source=Pfam & evalue<1e-10)").unwrap();
+        assert_eq!(
+            vec![
+                (Token::Quantifier(Quantifier::Any), 0),
+                (Token::OpenBracket, 3),
+                (
+                    Token::Field {
+                        field: Field::Source,
+                        op: CompareOp::Eq,
+                        value: "Pfam".to_string(),
+                        position: 11,
+                    },
+                    4
+                ),
+                (Token::And, 16),
+                (
+                    Token::Field {
+                        field: Field::Evalue,
+                        op: CompareOp::Lt,
+                        value: "1e-10".to_string(),
+                        position: 25,
+                    },
+                    18
+                ),
+                (Token::CloseBracket, 30),
+            ],
+            tokens
+        );
+
+        assert!(matches!(
+            lex("start in 1..300"),
+            Err(Error::Expr { position: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn test_spanned() {
+        let tokens = lex_spanned("ab & !(c)").unwrap();
+        let positions: Vec<usize> = tokens.iter().map(|(_, position)| *position).collect();
+        assert_eq!(positions, vec![0, 3, 5, 6, 7, 8]);
+    }
 }
@@ -2,8 +2,12 @@
 This parser is referred to https://github.com/Smittyvb/ttw/blob/f77fa34e62739b0225847317d243fc1a4ab29b96/taglogic/src/bool.rs#L187
 */
 
+pub mod arch;
 pub mod ast;
 pub mod expr;
 pub mod lex;
+pub mod pattern;
+pub mod predicate;
 
+pub use arch::Architecture;
 pub use expr::{Expr, MAX_RECURSION};
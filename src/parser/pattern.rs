@@ -0,0 +1,121 @@
+use crate::error::Error;
+
+/// A name matching many tags: a glob such as `PTHR10566*` or a regex written `/^PF0\d{4}$/`.
+///
+/// Patterns are compiled once when the expression is parsed. A `"quoted"` name is never
+/// one.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl Pattern {
+    /// Parse `text` as a pattern, or return `None` for a plain name. `position` is the
+    /// character offset of `text` in the expression, for errors.
+    pub fn parse(text: &str, position: usize) -> Result<Option<Self>, Error> {
+        if let Some(source) = regex_source(text) {
+            let source = source.map_err(|message| Error::expr(position, message))?;
+            return regex::Regex::new(&source)
+                .map(|x| Some(Self::Regex(x)))
+                .map_err(|err| Error::expr(position, format!("invalid regex: {}", err)));
+        }
+
+        if text.contains(['*', '?', '[']) {
+            return glob::Pattern::new(text)
+                .map(|x| Some(Self::Glob(x)))
+                .map_err(|err| {
+                    Error::expr(position + err.pos, format!("invalid pattern: {}", err.msg))
+                });
+        }
+
+        Ok(None)
+    }
+
+    pub fn is_match(&self, tag: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob.matches(tag),
+            Self::Regex(regex) => regex.is_match(tag),
+        }
+    }
+
+    /// The pattern as written in the expression.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Glob(glob) => glob.as_str(),
+            Self::Regex(regex) => regex.as_str(),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::Glob(_), Self::Glob(_)) | (Self::Regex(_), Self::Regex(_))
+        ) && self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+/// The regex of a `/regex/` name, with `\/` unescaped. `None` when `text` is not one.
+fn regex_source(text: &str) -> Option<Result<String, String>> {
+    let body = text.strip_prefix('/')?;
+
+    let mut source = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => source.push('/'),
+                Some(c) => {
+                    source.push('\\');
+                    source.push(c);
+                }
+                None => source.push('\\'),
+            },
+            '/' => {
+                let rest: String = chars.collect();
+                return Some(if rest.is_empty() {
+                    Ok(source)
+                } else {
+                    Err(format!("unexpected {:?} after regex", rest))
+                });
+            }
+            c => source.push(c),
+        }
+    }
+
+    Some(Err("unterminated regex".to_string()))
+}
+
+#[cfg(test)]
+mod test_pattern {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        let pattern = Pattern::parse("G3DSA:3.40.50.*", 0).unwrap().unwrap();
+        assert!(pattern.is_match("G3DSA:3.40.50.10190"));
+        assert!(!pattern.is_match("G3DSA:3.40.5.1"));
+
+        assert_eq!(Pattern::parse("PF00001", 0).unwrap(), None);
+    }
+
+    #[test]
+    fn regex() {
+        let pattern = Pattern::parse(r"/^PF0\d{4}$/", 0).unwrap().unwrap();
+        assert!(pattern.is_match("PF00533"));
+        assert!(!pattern.is_match("PF12738"));
+
+        let pattern = Pattern::parse(r"/^a\/b$/", 0).unwrap().unwrap();
+        assert!(pattern.is_match("a/b"));
+
+        assert!(matches!(
+            Pattern::parse("/PF", 4),
+            Err(Error::Expr { position: 4, .. })
+        ));
+        assert!(Pattern::parse("/(/", 0).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use super::lex::CompareOp;
+use super::pattern::Pattern;
+use crate::error::Error;
+use crate::records::DomainRecord;
+
+/// A field of a domain that predicates such as `evalue<1e-10` compare.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
+pub enum Field {
+    Name,
+    Source,
+    Desc,
+    /// [`DomainRecord::evalue`].
+    Evalue,
+    /// [`DomainRecord::profile_score`].
+    Score,
+    Start,
+    End,
+}
+
+impl Field {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let field = match name {
+            "name" => Self::Name,
+            "source" => Self::Source,
+            "desc" => Self::Desc,
+            "evalue" => Self::Evalue,
+            "score" => Self::Score,
+            "start" => Self::Start,
+            "end" => Self::End,
+            _ => return None,
+        };
+
+        Some(field)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Source => "source",
+            Self::Desc => "desc",
+            Self::Evalue => "evalue",
+            Self::Score => "score",
+            Self::Start => "start",
+            Self::End => "end",
+        }
+    }
+
+    /// Whether the field is compared as a number rather than as text.
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self, Self::Name | Self::Source | Self::Desc)
+    }
+
+    fn text<'a>(&self, domain: &'a DomainRecord) -> &'a str {
+        match self {
+            Self::Source => &domain.source,
+            Self::Desc => &domain.domain_desc,
+            _ => &domain.domain_name,
+        }
+    }
+
+    fn number(&self, domain: &DomainRecord) -> Option<f64> {
+        match self {
+            Self::Evalue => domain.evalue(),
+            Self::Score => domain.profile_score(),
+            Self::Start => Some(domain.start as f64),
+            Self::End => Some(domain.end as f64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Pattern(Pattern),
+    Number(f64),
+}
+
+/// A comparison on a field of one domain, such as `source=Pfam` or `start<300`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub field: Field,
+    pub op: CompareOp,
+    pub value: Value,
+    /// Character offset of the field name in the expression.
+    pub position: usize,
+}
+
+// numbers are never NaN, see `Predicate::parse`
+impl Eq for Predicate {}
+
+impl Predicate {
+    /// `position` and `value_position` are the character offsets of the field name and of
+    /// `value` in the expression.
+    pub fn parse(
+        field: Field,
+        op: CompareOp,
+        value: &str,
+        position: usize,
+        value_position: usize,
+    ) -> Result<Self, Error> {
+        let value = if field.is_numeric() {
+            let number = value.parse::<f64>().ok().filter(|x| !x.is_nan());
+            match number {
+                Some(number) => Value::Number(number),
+                None => {
+                    return Err(Error::expr(
+                        value_position,
+                        format!("invalid {}: {:?}", field.as_str(), value),
+                    ))
+                }
+            }
+        } else if !matches!(op, CompareOp::Eq | CompareOp::Ne) {
+            return Err(Error::expr(
+                position,
+                format!("only = and != compare {}", field.as_str()),
+            ));
+        } else {
+            match Pattern::parse(value, value_position)? {
+                Some(pattern) => Value::Pattern(pattern),
+                None => Value::Text(value.to_string()),
+            }
+        };
+
+        Ok(Self {
+            field,
+            op,
+            value,
+            position,
+        })
+    }
+
+    /// Whether `domain` satisfies the predicate. A domain without the field, such as the
+    /// E-value of a ProSiteProfiles match, never does.
+    pub fn holds(&self, domain: &DomainRecord) -> bool {
+        let text = self.field.text(domain);
+        match &self.value {
+            Value::Text(value) => self.op.holds(text, value.as_str()),
+            Value::Pattern(pattern) => self.op.holds(pattern.is_match(text), true),
+            Value::Number(value) => self
+                .field
+                .number(domain)
+                .is_some_and(|number| self.op.holds(number, *value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_predicate {
+    use super::*;
+
+    #[test]
+    fn holds() {
+        let domain = DomainRecord::new("Pfam", 10, 120, "PF00069", "Protein kinase domain")
+            .with_score(Some(1.5e-40));
+
+        let predicate = |field, op, value| Predicate::parse(field, op, value, 0, 0).unwrap();
+
+        assert!(predicate(Field::Source, CompareOp::Eq, "Pfam").holds(&domain));
+        assert!(predicate(Field::Name, CompareOp::Ne, "PF00400").holds(&domain));
+        assert!(predicate(Field::Desc, CompareOp::Eq, "/kinase/").holds(&domain));
+        assert!(!predicate(Field::Desc, CompareOp::Ne, "*kinase*").holds(&domain));
+        assert!(predicate(Field::Evalue, CompareOp::Lt, "1e-10").holds(&domain));
+        assert!(predicate(Field::Start, CompareOp::Ge, "10").holds(&domain));
+        assert!(!predicate(Field::End, CompareOp::Gt, "120").holds(&domain));
+
+        // Pfam reports E-values, not profile scores
+        assert!(!predicate(Field::Score, CompareOp::Ne, "0").holds(&domain));
+
+        assert!(matches!(
+            Predicate::parse(Field::Evalue, CompareOp::Lt, "small", 0, 7),
+            Err(Error::Expr { position: 7, .. })
+        ));
+        assert!(matches!(
+            Predicate::parse(Field::Source, CompareOp::Lt, "Pfam", 0, 7),
+            Err(Error::Expr { position: 0, .. })
+        ));
+    }
+}
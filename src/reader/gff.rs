@@ -0,0 +1,838 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+};
+
+use super::{Buffered, Diagnostic, LineReader, Records, Strictness};
+use crate::attributes::Attributes;
+use crate::error::Error;
+use crate::filter::{filter_methods, RecordFilter};
+use crate::records::{DomainRecord, GeneRecord};
+
+/// Parse one tab-separated GFF3 line into its sequence id and the match it describes.
+///
+/// Polypeptide lines come back as a [`DomainRecord`] whose source is `.`
+/// (see [`DomainRecord::is_gene`]). `line_no` and `offset` locate the line in the input
+/// and are only used for error reporting.
+pub fn parse_line(
+    line: &str,
+    line_no: usize,
+    offset: u64,
+) -> Result<(String, DomainRecord), Error> {
+    let line = line.trim();
+
+    let records: Vec<&str> = line.split('\t').collect();
+    if records.len() != 9 {
+        return Err(Error::MalformedLine {
+            line_no,
+            offset,
+            expected: "9",
+            columns: records.len(),
+        });
+    }
+
+    let coordinate = |column: usize| {
+        records[column - 1]
+            .parse::<u64>()
+            .map_err(|_| Error::InvalidCoordinate {
+                line_no,
+                offset,
+                column,
+                value: records[column - 1].to_string(),
+            })
+    };
+
+    let id = records[0];
+    let source = records[1];
+    let start = coordinate(4)?;
+    let end = coordinate(5)?;
+    let score = match records[5] {
+        "." => None,
+        value => Some(value.parse::<f64>().map_err(|_| Error::InvalidScore {
+            line_no,
+            offset,
+            column: 6,
+            value: value.to_string(),
+        })?),
+    };
+
+    let attributes = Attributes::parse(records[8]);
+    let domain_name = attributes.name().unwrap_or("No Name").to_string();
+    let domain_desc = attributes
+        .signature_desc()
+        .unwrap_or("No Description")
+        .to_string();
+
+    Ok((
+        id.to_string(),
+        DomainRecord::new(source, start, end, &domain_name, &domain_desc)
+            .with_attributes(attributes)
+            .with_score(score),
+    ))
+}
+
+/// Builder that reads InterProScan GFF3 and collects one [`GeneRecord`] per polypeptide.
+#[must_use]
+pub struct InterproGffReader<R: BufRead> {
+    reader: R,
+    comment: char,
+    finish_line: String,
+    filter: RecordFilter,
+    buffering: bool,
+    sequences: bool,
+    strictness: Strictness,
+}
+
+impl<R: BufRead> InterproGffReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            comment: '#',
+            finish_line: "## FASTA ##".to_string(),
+            filter: RecordFilter::default(),
+            buffering: false,
+            sequences: false,
+            strictness: Strictness::Strict,
+        }
+    }
+
+    /// Lines starting with `comment` are skipped. Defaults to `#`.
+    pub fn with_comment(mut self, comment: char) -> Self {
+        self.comment = comment;
+        self
+    }
+
+    /// Stop reading at the first line starting with `finish_line`. Defaults to `## FASTA ##`.
+    pub fn with_finish_line(mut self, finish_line: String) -> Self {
+        self.finish_line = finish_line;
+        self
+    }
+
+    filter_methods!(filter);
+
+    /// Hold every protein until the end of the input instead of yielding it as soon as its
+    /// block ends. Needed when matches are not grouped by sequence.
+    pub fn with_buffering(mut self, buffering: bool) -> Self {
+        self.buffering = buffering;
+        self
+    }
+
+    /// Read the sequences of the `##FASTA` section into [`GeneRecord::sequence`] instead of
+    /// stopping there. Each sequence is checked against the length and `md5` of its
+    /// polypeptide line.
+    ///
+    /// The sequences come after every match, so this holds all proteins until the end of
+    /// the input like [`InterproGffReader::with_buffering`].
+    pub fn with_sequences(mut self, sequences: bool) -> Self {
+        self.sequences = sequences;
+        self
+    }
+
+    /// Choose whether malformed lines abort reading or are skipped. Defaults to
+    /// [`Strictness::Strict`].
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Stream the proteins that pass every filter, one at a time.
+    pub fn records(self) -> GeneRecords<R> {
+        GeneRecords {
+            lines: LineReader::new(self.reader, self.strictness),
+            comment: self.comment,
+            finish_line: self.finish_line,
+            filter: self.filter,
+            buffering: self.buffering || self.sequences,
+            sequences: self.sequences,
+            fasta: None,
+            in_fasta: false,
+            current_id: None,
+            current: None,
+            yielded: HashSet::new(),
+            filtered: HashSet::new(),
+            orphans: HashMap::new(),
+            buffered: Buffered::default(),
+            drain: None,
+            finished: false,
+        }
+    }
+
+    /// Read the whole input and return the proteins that pass every filter.
+    ///
+    /// Lines skipped by a lenient reader are not reported; iterate over
+    /// [`InterproGffReader::records`] to get at them.
+    pub fn finish(self) -> Result<Vec<GeneRecord>, Error> {
+        self.with_buffering(true).records().collect()
+    }
+}
+
+/// Iterator over the proteins of an [`InterproGffReader`], see [`InterproGffReader::records`].
+///
+/// Proteins come out in the order their polypeptide lines appear in the input.
+///
+/// InterProScan writes the matches of a sequence right after its polypeptide line, so a
+/// protein is yielded as soon as the next polypeptide starts and only that one protein is
+/// held in memory. Matches that come before their polypeptide line are set aside until it
+/// shows up. A match for a protein that has already been yielded cannot be attached any
+/// more: it raises [`Error::UngroupedMatch`], or is skipped with a diagnostic in lenient
+/// mode. Use [`InterproGffReader::with_buffering`] for such inputs.
+///
+/// To tell these matches apart from those of proteins yet to come, the ids of the proteins
+/// already read are kept until the end of the input: memory still grows with the number of
+/// proteins, by their ids only, rather than with their matches.
+///
+/// Reading stops at the `##FASTA` section unless [`InterproGffReader::with_sequences`] is
+/// set.
+pub struct GeneRecords<R: BufRead> {
+    lines: LineReader<R>,
+    comment: char,
+    finish_line: String,
+    filter: RecordFilter,
+    buffering: bool,
+    sequences: bool,
+    fasta: Option<FastaEntry>,
+    in_fasta: bool,
+    current_id: Option<String>,
+    current: Option<GeneRecord>,
+    /// Proteins already yielded, or about to be, in streaming mode.
+    yielded: HashSet<String>,
+    /// Proteins whose polypeptide line failed the length filters.
+    filtered: HashSet<String>,
+    orphans: HashMap<String, Vec<DomainRecord>>,
+    buffered: Buffered,
+    drain: Option<std::vec::IntoIter<GeneRecord>>,
+    finished: bool,
+}
+
+/// A sequence of the `##FASTA` section being read.
+struct FastaEntry {
+    id: String,
+    sequence: String,
+    line_no: usize,
+    offset: u64,
+}
+
+impl<R: BufRead> GeneRecords<R> {
+    /// Read a single line and return the protein it completes, if any.
+    fn step(&mut self) -> Result<Option<GeneRecord>, Error> {
+        let (line, line_no, offset) = match self.lines.next_line()? {
+            Some(next) => next,
+            None => {
+                self.attach_sequence()?;
+                return Ok(self.end_of_input());
+            }
+        };
+
+        if line.starts_with(&self.finish_line) {
+            return Ok(self.end_of_input());
+        }
+
+        // the section starts with the directive, or implicitly with the first header
+        if !self.in_fasta && (line.starts_with("##FASTA") || line.starts_with('>')) {
+            if !self.sequences {
+                return Ok(self.end_of_input());
+            }
+            self.in_fasta = true;
+        }
+
+        if self.in_fasta {
+            if let Some(header) = line.strip_prefix('>') {
+                let id = header
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                self.attach_sequence()?;
+                self.fasta = Some(FastaEntry {
+                    id,
+                    sequence: String::new(),
+                    line_no,
+                    offset,
+                });
+            } else if let Some(entry) = self.fasta.as_mut() {
+                entry
+                    .sequence
+                    .extend(line.chars().filter(|x| !x.is_whitespace()));
+            }
+            return Ok(None);
+        }
+
+        if line.starts_with(self.comment) || line.len() == 1 {
+            return Ok(None);
+        }
+
+        let (id, domain) = match parse_line(line, line_no, offset) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.lines.tolerate(err)?;
+                return Ok(None);
+            }
+        };
+
+        if !self.filter.matches_id(&id)? {
+            return Ok(None);
+        }
+
+        if domain.is_gene() {
+            let completed = if self.buffering {
+                None
+            } else {
+                self.current.take()
+            };
+            if let Some(gene_record) = &completed {
+                self.yielded.insert(gene_record.id.clone());
+            }
+
+            let mut gene_record = GeneRecord::new(id.clone(), domain.start, domain.end)
+                .with_attributes(domain.attributes);
+            let orphans = self.orphans.remove(&id).unwrap_or_default();
+            if self.filter.is_in_length_range(&gene_record) {
+                for domain in orphans {
+                    gene_record.push_domain(domain);
+                }
+
+                if self.buffering {
+                    self.buffered.insert(gene_record);
+                } else {
+                    self.current = Some(gene_record);
+                }
+            } else {
+                self.filtered.insert(id.clone());
+            }
+            self.current_id = Some(id);
+
+            return Ok(completed);
+        }
+
+        if !self.filter.is_significant(&domain) {
+            return Ok(None);
+        }
+
+        if self.current_id.as_ref() == Some(&id) {
+            // the polypeptide of the current block may have been filtered out
+            if let Some(gene_record) = self.current.as_mut() {
+                gene_record.push_domain(domain);
+            } else if let Some(gene_record) = self.buffered.get_mut(&id) {
+                gene_record.push_domain(domain);
+            }
+        } else if let Some(gene_record) = self.buffered.get_mut(&id) {
+            gene_record.push_domain(domain);
+        } else if self.filtered.contains(&id) {
+            // the protein was filtered out: its matches are not needed
+        } else if self.yielded.contains(&id) {
+            self.lines.tolerate(Error::UngroupedMatch {
+                line_no,
+                offset,
+                id,
+            })?;
+        } else {
+            self.orphans.entry(id).or_default().push(domain);
+        }
+
+        Ok(None)
+    }
+
+    /// Check the sequence read last against its polypeptide line and attach it.
+    fn attach_sequence(&mut self) -> Result<(), Error> {
+        let entry = match self.fasta.take() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        // proteins dropped by a filter have no record left
+        let gene_record = match self.buffered.get_mut(&entry.id) {
+            Some(gene_record) => gene_record,
+            None => return Ok(()),
+        };
+
+        let message = if entry.sequence.len() as u64 != gene_record.length {
+            Some(format!(
+                "{} residues where the polypeptide has {}",
+                entry.sequence.len(),
+                gene_record.length
+            ))
+        } else {
+            let md5 = format!("{:x}", md5::compute(entry.sequence.as_bytes()));
+            match gene_record.md5() {
+                Some(expected) if !expected.eq_ignore_ascii_case(&md5) => Some(format!(
+                    "md5 {} where the polypeptide has {}",
+                    md5, expected
+                )),
+                _ => None,
+            }
+        };
+
+        match message {
+            Some(message) => self.lines.tolerate(Error::InvalidSequence {
+                line_no: entry.line_no,
+                offset: entry.offset,
+                id: entry.id,
+                message,
+            }),
+            None => {
+                gene_record.sequence = Some(entry.sequence);
+                Ok(())
+            }
+        }
+    }
+
+    fn end_of_input(&mut self) -> Option<GeneRecord> {
+        self.finished = true;
+
+        if !self.orphans.is_empty() {
+            let n_matches: usize = self.orphans.values().map(|domains| domains.len()).sum();
+            warn!(
+                "dropped {} matches on {} sequences without a polypeptide line",
+                n_matches,
+                self.orphans.len()
+            );
+            self.orphans.clear();
+        }
+
+        self.drain = Some(self.buffered.drain());
+        self.current.take()
+    }
+}
+
+impl<R: BufRead> Iterator for GeneRecords<R> {
+    type Item = Result<GeneRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let completed = if let Some(drain) = self.drain.as_mut() {
+                Some(drain.next()?)
+            } else if self.finished {
+                return None;
+            } else {
+                match self.step() {
+                    Ok(completed) => completed,
+                    Err(err) => return Some(Err(err)),
+                }
+            };
+
+            if let Some(gene_record) = completed.and_then(|x| self.filter.finalize(x)) {
+                return Some(Ok(gene_record));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Records for GeneRecords<R> {
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.lines.diagnostics
+    }
+
+    fn skipped_lines(&self) -> usize {
+        self.lines.skipped_lines
+    }
+}
+
+#[cfg(test)]
+mod test_reader {
+    use super::*;
+    use crate::compression::{Compression, DecoderReader};
+    use crate::parser::{Architecture, Expr};
+    use crate::reader::read_with_gz;
+    use flate2::read::MultiGzDecoder;
+    use std::io::BufReader;
+
+    const POLYPEPTIDE_A: &str = "a\t.\tpolypeptide\t1\t100\t.\t+\t.\tID=a";
+    const POLYPEPTIDE_B: &str = "b\t.\tpolypeptide\t1\t200\t.\t+\t.\tID=b";
+    const MATCH_A: &str = "a\tPfam\tprotein_match\t10\t50\t1.0E-10\t+\t.\tName=PF00001";
+    const MATCH_B: &str = "b\tPfam\tprotein_match\t20\t60\t1.0E-10\t+\t.\tName=PF00002";
+
+    fn read(lines: &[&str], buffering: bool) -> Vec<GeneRecord> {
+        let input = lines.join("\n");
+        InterproGffReader::new(input.as_bytes())
+            .with_buffering(buffering)
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn ids(records: &[GeneRecord]) -> Vec<&str> {
+        records.iter().map(|x| x.id.as_str()).collect()
+    }
+
+    fn domain_names(record: &GeneRecord) -> Vec<&str> {
+        record
+            .iter_domains()
+            .map(|domain| domain.domain_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn stream_small() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        let streamed: Vec<GeneRecord> = InterproGffReader::new(read_with_gz(&path).unwrap())
+            .records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let finished = InterproGffReader::new(read_with_gz(&path).unwrap())
+            .finish()
+            .unwrap();
+
+        assert_eq!(streamed.len(), 75);
+        assert_eq!(ids(&streamed), ids(&finished));
+        assert_eq!(streamed[0].id, "kfl00063_0090_v1.1");
+        for (record, other) in streamed.iter().zip(finished.iter()) {
+            assert_eq!(domain_names(record), domain_names(other));
+        }
+    }
+
+    #[test]
+    fn stream_grouped() {
+        let records = read(&[POLYPEPTIDE_A, MATCH_A, POLYPEPTIDE_B, MATCH_B], false);
+        assert_eq!(records.len(), 2);
+        assert_eq!(domain_names(&records[0]), vec!["PF00001"]);
+        assert_eq!(domain_names(&records[1]), vec!["PF00002"]);
+    }
+
+    #[test]
+    fn match_before_polypeptide() {
+        let records = read(&[MATCH_A, POLYPEPTIDE_B, MATCH_B, POLYPEPTIDE_A], false);
+        assert_eq!(ids(&records), vec!["b", "a"]);
+        assert_eq!(domain_names(&records[0]), vec!["PF00002"]);
+        assert_eq!(domain_names(&records[1]), vec!["PF00001"]);
+    }
+
+    #[test]
+    fn interleaved_needs_buffering() {
+        let lines = [POLYPEPTIDE_A, POLYPEPTIDE_B, MATCH_A, MATCH_B];
+        let input = lines.join("\n");
+
+        let err = InterproGffReader::new(input.as_bytes())
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UngroupedMatch { line_no: 3, ref id, .. } if id == "a"
+        ));
+
+        let mut records = InterproGffReader::new(input.as_bytes())
+            .with_strictness(Strictness::Lenient)
+            .records();
+        let collected: Vec<GeneRecord> = records.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids(&collected), vec!["a", "b"]);
+        assert_eq!(records.skipped_lines(), 1);
+        assert!(matches!(
+            records.diagnostics()[0].error,
+            Error::UngroupedMatch { line_no: 3, .. }
+        ));
+
+        let buffered = read(&lines, true);
+        assert_eq!(ids(&buffered), vec!["a", "b"]);
+        assert_eq!(domain_names(&buffered[0]), vec!["PF00001"]);
+        assert_eq!(domain_names(&buffered[1]), vec!["PF00002"]);
+    }
+
+    #[test]
+    fn late_match() {
+        let late = MATCH_A.replace("PF00001", "PF00003");
+        let input = [POLYPEPTIDE_A, MATCH_A, POLYPEPTIDE_B, MATCH_B, &late].join("\n");
+
+        let err = InterproGffReader::new(input.as_bytes())
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(matches!(err, Error::UngroupedMatch { line_no: 5, .. }));
+
+        let records = InterproGffReader::new(input.as_bytes())
+            .with_domain_expr(Some(Expr::from_string("PF00003").unwrap()))
+            .finish()
+            .unwrap();
+        assert_eq!(ids(&records), vec!["a"]);
+        assert_eq!(domain_names(&records[0]), vec!["PF00001", "PF00003"]);
+    }
+
+    #[test]
+    fn late_match_filtered_out() {
+        let late = MATCH_B.replace("PF00002", "PF00003");
+        let polypeptide_c = POLYPEPTIDE_A.replace("a", "c");
+        let input = [
+            POLYPEPTIDE_B,
+            MATCH_B,
+            POLYPEPTIDE_A,
+            MATCH_A,
+            &late,
+            &polypeptide_c,
+        ]
+        .join("\n");
+
+        let mut records = InterproGffReader::new(input.as_bytes())
+            .with_max_length(Some(150))
+            .records();
+        assert_eq!(records.next().unwrap().unwrap().id, "a");
+        // the late match of b is dropped rather than kept until the end of the input
+        assert!(records.orphans.is_empty());
+        assert_eq!(records.next().unwrap().unwrap().id, "c");
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn error_location() {
+        let input = [POLYPEPTIDE_A, MATCH_A, "a\tPfam\tprotein_match\tten\t50"].join("\n");
+        let err = InterproGffReader::new(input.as_bytes())
+            .finish()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MalformedLine {
+                line_no: 3,
+                columns: 5,
+                ..
+            }
+        ));
+
+        let input = [POLYPEPTIDE_A, &MATCH_A.replace("\t10\t", "\tten\t")].join("\n");
+        let err = InterproGffReader::new(input.as_bytes())
+            .finish()
+            .unwrap_err();
+        match err {
+            Error::InvalidCoordinate {
+                line_no,
+                offset,
+                column,
+                value,
+            } => {
+                assert_eq!(line_no, 2);
+                assert_eq!(offset, POLYPEPTIDE_A.len() as u64 + 1);
+                assert_eq!(column, 4);
+                assert_eq!(value, "ten");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn broken_attribute() {
+        let records = read(&[POLYPEPTIDE_A, &format!("{};broken", MATCH_A)], false);
+        assert_eq!(domain_names(&records[0]), vec!["PF00001"]);
+    }
+
+    #[test]
+    fn gzip_error() {
+        let input = BufReader::new(DecoderReader::new(
+            Compression::Gzip,
+            MultiGzDecoder::new(&b"\x1f\x8bnot gzip"[..]),
+        ));
+        let err = InterproGffReader::new(input).finish().unwrap_err();
+        assert!(matches!(err, Error::Gzip(_)));
+    }
+
+    #[test]
+    fn lenient() {
+        let truncated = "b\tPfam\tprotein_match\t20";
+        let input = [POLYPEPTIDE_A, MATCH_A, truncated, POLYPEPTIDE_B, MATCH_B].join("\n");
+
+        let mut records = InterproGffReader::new(input.as_bytes())
+            .with_strictness(Strictness::Lenient)
+            .records();
+        let collected: Vec<GeneRecord> = records.by_ref().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(ids(&collected), vec!["a", "b"]);
+        assert_eq!(records.skipped_lines(), 1);
+        assert_eq!(records.diagnostics()[0].line_no, 3);
+        assert!(matches!(
+            records.diagnostics()[0].error,
+            Error::MalformedLine { columns: 4, .. }
+        ));
+    }
+
+    #[test]
+    fn lenient_truncated_gzip() {
+        let mut compressed = vec![];
+        {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut compressed, flate2::Compression::default());
+            std::io::Write::write_all(&mut encoder, [POLYPEPTIDE_A, MATCH_A].join("\n").as_bytes())
+                .unwrap();
+        }
+        compressed.truncate(compressed.len() - 4);
+
+        let input = BufReader::new(DecoderReader::new(
+            Compression::Gzip,
+            MultiGzDecoder::new(&compressed[..]),
+        ));
+        let mut records = InterproGffReader::new(input)
+            .with_strictness(Strictness::Lenient)
+            .records();
+        let collected: Vec<GeneRecord> = records.by_ref().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(ids(&collected), vec!["a"]);
+        assert!(matches!(records.diagnostics()[0].error, Error::Gzip(_)));
+    }
+
+    #[test]
+    fn attributes() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        let records = InterproGffReader::new(read_with_gz(&path).unwrap())
+            .finish()
+            .unwrap();
+
+        assert_eq!(records[0].md5(), Some("4a9d57d71a755d8530879de3c58a2545"));
+        let domain = records[0].iter_domains().nth(1).unwrap();
+        assert_eq!(domain.domain_name, "PS50172");
+        assert_eq!(domain.dbxref(), &["InterPro:IPR001357".to_string()]);
+        assert_eq!(domain.attributes.target().unwrap().start, 99);
+    }
+
+    #[test]
+    fn score_filters() {
+        let profile = "a\tProSiteProfiles\tprotein_match\t10\t50\t14.3\t+\t.\tName=PS50172";
+        let weak = "a\tPfam\tprotein_match\t60\t90\t0.01\t+\t.\tName=PF00003";
+        let disorder = "a\tMobiDBLite\tprotein_match\t1\t9\t.\t+\t.\tName=mobidb-lite";
+        // a probability and a score of no known kind, neither of them an E-value
+        let signal = "a\tSignalP\tprotein_match\t1\t20\t0.9\t+\t.\tName=SignalP-noTM";
+        let helix = "a\tPhobius\tprotein_match\t70\t95\t3.0\t+\t.\tName=TRANSMEMBRANE";
+        let input = [
+            POLYPEPTIDE_A,
+            MATCH_A,
+            profile,
+            weak,
+            disorder,
+            signal,
+            helix,
+        ]
+        .join("\n");
+
+        let records = InterproGffReader::new(input.as_bytes()).finish().unwrap();
+        let domains: Vec<&DomainRecord> = records[0].iter_domains().collect();
+        assert_eq!(domains[0].evalue(), Some(1.0E-10));
+        assert_eq!(domains[1].evalue(), None);
+        assert_eq!(domains[1].profile_score(), Some(14.3));
+        assert_eq!(domains[3].score, None);
+        assert_eq!(domains[4].evalue(), None);
+        assert_eq!(domains[5].profile_score(), None);
+
+        let records = InterproGffReader::new(input.as_bytes())
+            .with_max_evalue(Some(1e-5))
+            .with_min_score(Some(15.0))
+            .finish()
+            .unwrap();
+        assert_eq!(
+            domain_names(&records[0]),
+            vec!["PF00001", "mobidb-lite", "SignalP-noTM", "TRANSMEMBRANE"]
+        );
+    }
+
+    #[test]
+    fn interpro_expr() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        let records = InterproGffReader::new(read_with_gz(&path).unwrap())
+            .with_interpro_expr(Some(Expr::from_string("IPR001357 & IPR036420").unwrap()))
+            .finish()
+            .unwrap();
+
+        assert!(!records.is_empty());
+        for record in records.iter() {
+            let accessions: Vec<&str> = record
+                .iter_domains()
+                .flat_map(|x| x.interpro_accessions())
+                .collect();
+            assert!(accessions.contains(&"IPR001357"));
+            assert!(accessions.contains(&"IPR036420"));
+        }
+    }
+
+    #[test]
+    fn arch() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        let records = InterproGffReader::new(read_with_gz(&path).unwrap())
+            .with_source_expr(Some(Expr::from_string("Pfam").unwrap()))
+            .with_arch(Some(
+                Architecture::from_string("^PF12738{2} > PF16589 > PF12738$").unwrap(),
+            ))
+            .finish()
+            .unwrap();
+
+        assert_eq!(ids(&records), vec!["kfl00063_0090_v1.1"]);
+    }
+
+    #[test]
+    fn fasta_section() {
+        let sequence_a = "M".repeat(100);
+        let polypeptide_a = format!(
+            "{};md5={:x}",
+            POLYPEPTIDE_A,
+            md5::compute(sequence_a.as_bytes())
+        );
+        let input = [
+            polypeptide_a.as_str(),
+            MATCH_A,
+            POLYPEPTIDE_B,
+            MATCH_B,
+            "##FASTA",
+            ">a",
+            &sequence_a[..60],
+            &sequence_a[60..],
+            ">b description",
+            &"K".repeat(200),
+        ]
+        .join("\n");
+
+        let records = InterproGffReader::new(input.as_bytes()).finish().unwrap();
+        assert_eq!(ids(&records), vec!["a", "b"]);
+        assert!(records.iter().all(|x| x.sequence.is_none()));
+
+        let records = InterproGffReader::new(input.as_bytes())
+            .with_sequences(true)
+            .finish()
+            .unwrap();
+        assert_eq!(records[0].sequence.as_deref(), Some(sequence_a.as_str()));
+        assert_eq!(records[1].sequence, Some("K".repeat(200)));
+    }
+
+    #[test]
+    fn fasta_mismatch() {
+        let polypeptide_a = format!("{};md5={:x}", POLYPEPTIDE_A, md5::compute("other"));
+        let sequence_a = "M".repeat(100);
+        let input = [
+            polypeptide_a.as_str(),
+            POLYPEPTIDE_B,
+            "##FASTA",
+            ">a",
+            &sequence_a,
+            ">b",
+            "KKK",
+        ]
+        .join("\n");
+
+        let result = InterproGffReader::new(input.as_bytes())
+            .with_sequences(true)
+            .finish();
+        assert!(matches!(
+            result,
+            Err(Error::InvalidSequence { line_no: 4, .. })
+        ));
+
+        let mut records = InterproGffReader::new(input.as_bytes())
+            .with_sequences(true)
+            .with_strictness(Strictness::Lenient)
+            .records();
+        let collected: Vec<GeneRecord> = records.by_ref().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(ids(&collected), vec!["a", "b"]);
+        assert!(collected.iter().all(|x| x.sequence.is_none()));
+        assert_eq!(records.skipped_lines(), 2);
+        assert_eq!(records.diagnostics()[1].line_no, 6);
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        let records = InterproGffReader::new(read_with_gz(&path).unwrap())
+            .finish()
+            .unwrap();
+
+        let mut written = vec!["##gff-version 3".to_string()];
+        for record in records.iter() {
+            written.extend(record.to_gff_lines());
+        }
+        let written = written.join("\n");
+        let read_back = InterproGffReader::new(written.as_bytes()).finish().unwrap();
+
+        assert_eq!(format!("{:?}", read_back), format!("{:?}", records));
+    }
+}
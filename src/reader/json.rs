@@ -0,0 +1,527 @@
+use std::{collections::VecDeque, io::BufRead};
+
+use serde::Deserialize;
+
+use super::matches::{Entry, Location, Match, Protein};
+use super::{Diagnostic, Records};
+use crate::attributes::Attributes;
+use crate::error::Error;
+use crate::filter::{filter_methods, RecordFilter};
+use crate::records::GeneRecord;
+
+/// Builder that reads InterProScan JSON (`-f json`) into the same [`GeneRecord`]s as
+/// [`InterproGffReader`](super::InterproGffReader).
+///
+/// Only one element of the `results` array is held in memory at a time. Proteins keep
+/// their sequence, and locations carry the same extra attributes as with
+/// [`InterproXmlReader`](super::InterproXmlReader).
+#[must_use]
+pub struct InterproJsonReader<R: BufRead> {
+    reader: R,
+    filter: RecordFilter,
+}
+
+impl<R: BufRead> InterproJsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            filter: RecordFilter::default(),
+        }
+    }
+
+    filter_methods!(filter);
+
+    /// Stream the proteins that pass every filter, one at a time.
+    pub fn records(self) -> JsonRecords<R> {
+        JsonRecords {
+            scanner: Scanner {
+                reader: self.reader,
+                offset: 0,
+            },
+            filter: self.filter,
+            pending: VecDeque::new(),
+            started: false,
+            finished: false,
+            element: Vec::new(),
+        }
+    }
+
+    /// Read the whole input and return the proteins that pass every filter.
+    pub fn finish(self) -> Result<Vec<GeneRecord>, Error> {
+        self.records().collect()
+    }
+}
+
+/// Iterator over the proteins of an [`InterproJsonReader`], see [`InterproJsonReader::records`].
+///
+/// Like in XML, identical sequences are written once with several `xref`s; each id comes
+/// out as its own [`GeneRecord`].
+pub struct JsonRecords<R: BufRead> {
+    scanner: Scanner<R>,
+    filter: RecordFilter,
+    pending: VecDeque<GeneRecord>,
+    started: bool,
+    finished: bool,
+    element: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct JsonProtein {
+    sequence: Option<String>,
+    md5: Option<String>,
+    #[serde(default)]
+    matches: Vec<JsonMatch>,
+    #[serde(default)]
+    xref: Vec<JsonXref>,
+}
+
+#[derive(Deserialize)]
+struct JsonXref {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct JsonMatch {
+    signature: JsonSignature,
+    #[serde(default)]
+    locations: Vec<JsonLocation>,
+    evalue: Option<f64>,
+    score: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSignature {
+    accession: String,
+    name: Option<String>,
+    description: Option<String>,
+    signature_library_release: Option<JsonLibrary>,
+    entry: Option<JsonEntry>,
+}
+
+#[derive(Deserialize)]
+struct JsonLibrary {
+    library: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEntry {
+    accession: String,
+    description: Option<String>,
+    #[serde(rename = "type")]
+    entry_type: Option<String>,
+    #[serde(default, rename = "goXRefs")]
+    go_xrefs: Vec<JsonXref>,
+    #[serde(default, rename = "pathwayXRefs")]
+    pathway_xrefs: Vec<JsonPathway>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonPathway {
+    database_name: String,
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLocation {
+    start: u64,
+    end: u64,
+    evalue: Option<f64>,
+    score: Option<f64>,
+    hmm_start: Option<u64>,
+    hmm_end: Option<u64>,
+    hmm_length: Option<u64>,
+    hmm_bounds: Option<String>,
+    envelope_start: Option<u64>,
+    envelope_end: Option<u64>,
+    alignment: Option<String>,
+}
+
+impl From<JsonProtein> for Protein {
+    fn from(protein: JsonProtein) -> Self {
+        Self {
+            md5: protein.md5,
+            sequence: protein.sequence.unwrap_or_default(),
+            ids: protein.xref.into_iter().map(|x| x.id).collect(),
+            domains: protein
+                .matches
+                .into_iter()
+                .flat_map(|x| Match::from(x).into_domains())
+                .collect(),
+        }
+    }
+}
+
+impl From<JsonMatch> for Match {
+    fn from(json: JsonMatch) -> Self {
+        let signature = json.signature;
+        let (go_terms, pathways) = match &signature.entry {
+            Some(entry) => (
+                entry.go_xrefs.iter().map(|x| x.id.clone()).collect(),
+                entry
+                    .pathway_xrefs
+                    .iter()
+                    .map(|x| format!("{}:{}", x.database_name, x.id))
+                    .collect(),
+            ),
+            None => (vec![], vec![]),
+        };
+
+        Self {
+            evalue: json.evalue,
+            score: json.score,
+            accession: signature.accession,
+            name: signature.name,
+            desc: signature.description,
+            library: signature
+                .signature_library_release
+                .map(|x| x.library)
+                .unwrap_or_default(),
+            entry: signature.entry.map(|entry| Entry {
+                accession: entry.accession,
+                desc: entry.description,
+                entry_type: entry.entry_type,
+            }),
+            go_terms,
+            pathways,
+            locations: json.locations.into_iter().map(Location::from).collect(),
+        }
+    }
+}
+
+impl From<JsonLocation> for Location {
+    fn from(json: JsonLocation) -> Self {
+        let mut attributes = Attributes::default();
+        let fields = [
+            ("hmm_start", json.hmm_start.map(|x| x.to_string())),
+            ("hmm_end", json.hmm_end.map(|x| x.to_string())),
+            ("hmm_length", json.hmm_length.map(|x| x.to_string())),
+            ("hmm_bounds", json.hmm_bounds),
+            ("env_start", json.envelope_start.map(|x| x.to_string())),
+            ("env_end", json.envelope_end.map(|x| x.to_string())),
+            ("alignment", json.alignment),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                attributes.push(key, value);
+            }
+        }
+
+        Self {
+            start: json.start,
+            end: json.end,
+            evalue: json.evalue,
+            score: json.score,
+            attributes,
+        }
+    }
+}
+
+/// Cuts the elements of the `results` array out of the input without parsing them.
+struct Scanner<R> {
+    reader: R,
+    offset: u64,
+}
+
+impl<R: BufRead> Scanner<R> {
+    fn error<S: ToString>(&self, message: S) -> Error {
+        Error::Json {
+            offset: self.offset,
+            message: message.to_string(),
+        }
+    }
+
+    /// The next byte that is not whitespace, left unconsumed. `None` at the end of input.
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+
+            match buf.iter().position(|x| !x.is_ascii_whitespace()) {
+                Some(n) => {
+                    let byte = buf[n];
+                    self.reader.consume(n);
+                    self.offset += n as u64;
+                    return Ok(Some(byte));
+                }
+                None => {
+                    let n = buf.len();
+                    self.reader.consume(n);
+                    self.offset += n as u64;
+                }
+            }
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        match self.peek()? {
+            Some(x) if x == byte => {
+                self.reader.consume(1);
+                self.offset += 1;
+                Ok(())
+            }
+            Some(x) => Err(self.error(format!(
+                "expected {:?}, found {:?}",
+                byte as char, x as char
+            ))),
+            None => Err(self.error(format!("expected {:?}, found end of input", byte as char))),
+        }
+    }
+
+    /// Move past the `[` of the `results` array. `false` when the document has none.
+    ///
+    /// A document that is an array itself is taken as the `results` array.
+    fn seek_results(&mut self) -> Result<bool, Error> {
+        match self.peek()? {
+            Some(b'[') => return self.expect(b'[').map(|_| true),
+            None => return Ok(false),
+            _ => self.expect(b'{')?,
+        }
+
+        let mut key = Vec::new();
+        loop {
+            match self.peek()? {
+                Some(b'}') => return Ok(false),
+                Some(b',') => self.expect(b',')?,
+                _ => {}
+            }
+
+            key.clear();
+            if self.peek()? != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            self.value(&mut key)?;
+            self.expect(b':')?;
+
+            if key == b"\"results\"" {
+                self.expect(b'[')?;
+                return Ok(true);
+            }
+            self.value(&mut Vec::new())?;
+        }
+    }
+
+    /// Copy the next element of the array being scanned into `element`. `false` at the end
+    /// of the array.
+    fn next_element(&mut self, element: &mut Vec<u8>) -> Result<bool, Error> {
+        match self.peek()? {
+            Some(b']') => {
+                self.expect(b']')?;
+                return Ok(false);
+            }
+            Some(b',') => self.expect(b',')?,
+            _ => {}
+        }
+
+        element.clear();
+        self.value(element)?;
+        Ok(true)
+    }
+
+    /// Copy one complete JSON value into `out`, tracking nesting and strings.
+    fn value(&mut self, out: &mut Vec<u8>) -> Result<(), Error> {
+        let is_scalar = match self.peek()? {
+            Some(b'{') | Some(b'[') | Some(b'"') => false,
+            Some(_) => true,
+            None => return Err(self.error("unexpected end of input")),
+        };
+
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Err(self.error("unexpected end of input"));
+            }
+
+            let mut end = None;
+            for (i, &byte) in buf.iter().enumerate() {
+                if in_string {
+                    if escaped {
+                        escaped = false;
+                    } else if byte == b'\\' {
+                        escaped = true;
+                    } else if byte == b'"' {
+                        in_string = false;
+                        if depth == 0 {
+                            end = Some(i + 1);
+                            break;
+                        }
+                    }
+                    continue;
+                }
+
+                if is_scalar {
+                    if matches!(byte, b',' | b']' | b'}') || byte.is_ascii_whitespace() {
+                        end = Some(i);
+                        break;
+                    }
+                    continue;
+                }
+
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = Some(i + 1);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let n = end.unwrap_or(buf.len());
+            out.extend_from_slice(&buf[..n]);
+            self.reader.consume(n);
+            self.offset += n as u64;
+
+            if end.is_some() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R: BufRead> JsonRecords<R> {
+    /// Cut the next protein out of the input, `None` when there is none left.
+    fn next_protein(&mut self) -> Result<Option<(Protein, u64)>, Error> {
+        if !self.started {
+            self.started = true;
+            if !self.scanner.seek_results()? {
+                return Ok(None);
+            }
+        }
+
+        let offset = self.scanner.offset;
+        if !self.scanner.next_element(&mut self.element)? {
+            return Ok(None);
+        }
+
+        let protein: JsonProtein =
+            serde_json::from_slice(&self.element).map_err(|err| Error::Json {
+                offset,
+                message: err.to_string(),
+            })?;
+
+        Ok(Some((Protein::from(protein), offset)))
+    }
+}
+
+impl<R: BufRead> Iterator for JsonRecords<R> {
+    type Item = Result<GeneRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(gene_record) = self.pending.pop_front() {
+                match self.filter.apply(gene_record) {
+                    Ok(Some(gene_record)) => return Some(Ok(gene_record)),
+                    Ok(None) => continue,
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            let records = match self.next_protein() {
+                Ok(Some((protein, offset))) => protein
+                    .into_records()
+                    .map_err(|message| Error::Json { offset, message }),
+                Ok(None) => {
+                    self.finished = true;
+                    continue;
+                }
+                Err(err) => Err(err),
+            };
+
+            match records {
+                Ok(records) => self.pending.extend(records),
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Records for JsonRecords<R> {
+    /// JSON cannot be resynchronised after an error, so nothing is ever skipped.
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &[]
+    }
+
+    fn skipped_lines(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test_json {
+    use super::*;
+    use crate::parser::Expr;
+    use crate::reader::{read_with_gz, InterproXmlReader};
+
+    fn read() -> InterproJsonReader<Box<dyn BufRead>> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.json");
+        InterproJsonReader::new(read_with_gz(&path).unwrap())
+    }
+
+    #[test]
+    fn same_as_xml() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.xml");
+        let from_xml = InterproXmlReader::new(read_with_gz(&path).unwrap())
+            .finish()
+            .unwrap();
+        let from_json = read().finish().unwrap();
+
+        assert_eq!(from_json.len(), 3);
+        for (json, xml) in from_json.iter().zip(from_xml.iter()) {
+            assert_eq!(json.id, xml.id);
+            assert_eq!(json.sequence, xml.sequence);
+            assert_eq!(json.attributes, xml.attributes);
+            assert_eq!(json.to_tsv_line(), xml.to_tsv_line());
+            for (x, y) in json.iter_domains().zip(xml.iter_domains()) {
+                assert_eq!(x.score, y.score);
+                assert_eq!(x.attributes, y.attributes);
+            }
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let records = read()
+            .with_id_expr(Some(Expr::from_string("!alias_1").unwrap()))
+            .with_min_length(Some(100))
+            .finish()
+            .unwrap();
+
+        let ids: Vec<&str> = records.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["kfl00063_0090_v1.1"]);
+    }
+
+    #[test]
+    fn results_array() {
+        let input = r#"{"interproscan-version": "5.54-87.0", "note": {"results": []},
+            "results": [{"sequence": "MSTN", "md5": "x", "matches": [], "xref": [{"id": "a"}]}]}"#;
+        let records = InterproJsonReader::new(input.as_bytes()).finish().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].length, 4);
+
+        let input = r#"{"results": [{"sequence": "MSTN", "xref": [{"id": "a"}]},"#;
+        let err = InterproJsonReader::new(input.as_bytes())
+            .finish()
+            .unwrap_err();
+        assert!(matches!(err, Error::Json { .. }));
+    }
+}
@@ -0,0 +1,139 @@
+//! The protein model shared by the XML and JSON readers, which both describe a protein
+//! once with all its matches and locations nested inside.
+
+use crate::attributes::Attributes;
+use crate::records::{DomainRecord, GeneRecord};
+use crate::utils::{score_kind, source_name, ScoreKind};
+
+/// A protein with every id it was submitted under.
+#[derive(Default)]
+pub(super) struct Protein {
+    pub md5: Option<String>,
+    pub sequence: String,
+    pub ids: Vec<String>,
+    pub domains: Vec<DomainRecord>,
+}
+
+/// A signature matched on a protein, possibly at several locations.
+#[derive(Default)]
+pub(super) struct Match {
+    pub evalue: Option<f64>,
+    pub score: Option<f64>,
+    pub accession: String,
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    /// Library of the signature in upper case, such as `PFAM`.
+    pub library: String,
+    pub entry: Option<Entry>,
+    pub go_terms: Vec<String>,
+    /// Pathway cross-references as `Reactome:R-HSA-5693565`.
+    pub pathways: Vec<String>,
+    pub locations: Vec<Location>,
+}
+
+/// The InterPro entry a signature is integrated into.
+pub(super) struct Entry {
+    pub accession: String,
+    pub desc: Option<String>,
+    pub entry_type: Option<String>,
+}
+
+/// Where a signature matched.
+pub(super) struct Location {
+    pub start: u64,
+    pub end: u64,
+    pub evalue: Option<f64>,
+    pub score: Option<f64>,
+    /// Details GFF3 lacks, such as `hmm_start` or `alignment`.
+    pub attributes: Attributes,
+}
+
+impl Protein {
+    /// One [`GeneRecord`] for every id of the protein. Fails with a message when the
+    /// protein has no sequence to take the length from.
+    pub fn into_records(self) -> Result<Vec<GeneRecord>, String> {
+        if self.sequence.is_empty() {
+            return Err(format!("no sequence for {}", self.ids.join(",")));
+        }
+
+        let records = self
+            .ids
+            .iter()
+            .map(|id| {
+                let mut attributes = Attributes::default();
+                attributes.push("ID", id);
+                if let Some(md5) = &self.md5 {
+                    attributes.push("md5", md5);
+                }
+
+                let mut gene_record = GeneRecord::new(id.clone(), 1, self.sequence.len() as u64)
+                    .with_attributes(attributes)
+                    .with_sequence(Some(self.sequence.clone()));
+                for domain in self.domains.iter() {
+                    gene_record.push_domain(domain.clone());
+                }
+                gene_record
+            })
+            .collect();
+
+        Ok(records)
+    }
+}
+
+impl Match {
+    /// One [`DomainRecord`] for every location of the match.
+    pub fn into_domains(self) -> Vec<DomainRecord> {
+        let source = source_name(&self.library);
+        let signature_desc = self.desc.clone().or_else(|| self.name.clone());
+
+        let mut attributes = Attributes::default();
+        attributes.push("Name", &self.accession);
+        if let Some(signature_desc) = &signature_desc {
+            attributes.push("signature_desc", signature_desc);
+        }
+        if let Some(entry) = &self.entry {
+            attributes.push("Dbxref", format!("InterPro:{}", entry.accession));
+        }
+        if !self.pathways.is_empty() {
+            attributes.extend("Dbxref", self.pathways.clone());
+        }
+        if !self.go_terms.is_empty() {
+            attributes.extend("Ontology_term", self.go_terms.clone());
+        }
+        if let Some(entry) = &self.entry {
+            if let Some(desc) = &entry.desc {
+                attributes.push("interpro_desc", desc);
+            }
+            if let Some(entry_type) = &entry.entry_type {
+                attributes.push("interpro_type", entry_type);
+            }
+        }
+
+        let desc = signature_desc.unwrap_or_else(|| "No Description".to_string());
+        self.locations
+            .into_iter()
+            .map(|location| {
+                let score = match score_kind(&source) {
+                    Some(ScoreKind::EValue) => location.evalue.or(self.evalue),
+                    Some(ScoreKind::Score) => location.score.or(self.score),
+                    None => None,
+                };
+
+                let mut location_attributes = attributes.clone();
+                for (key, values) in location.attributes.iter() {
+                    location_attributes.extend(key, values.to_vec());
+                }
+
+                DomainRecord::new(
+                    source.as_str(),
+                    location.start,
+                    location.end,
+                    &self.accession,
+                    &desc,
+                )
+                .with_attributes(location_attributes)
+                .with_score(score)
+            })
+            .collect()
+    }
+}
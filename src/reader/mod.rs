@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::compression::decompress;
+use crate::error::Error;
+use crate::records::GeneRecord;
+
+mod gff;
+mod json;
+mod matches;
+mod tsv;
+mod xml;
+
+pub use gff::{parse_line, GeneRecords, InterproGffReader};
+pub use json::{InterproJsonReader, JsonRecords};
+pub use tsv::{parse_tsv_line, InterproTsvReader, TsvRecords};
+pub use xml::{InterproXmlReader, XmlRecords};
+
+/// Open `p` for buffered reading, or standard input when `p` is `-`. Compressed input is
+/// recognized by its magic bytes and decompressed on the fly, whatever its extension; see
+/// [`crate::compression`] for the supported formats.
+pub fn read_with_gz<P: AsRef<Path>>(p: &P) -> Result<Box<dyn BufRead>, Error> {
+    let input: Box<dyn Read> = if p.as_ref() == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(p)?)
+    };
+
+    decompress(BufReader::new(input))
+}
+
+/// The output formats of InterProScan this crate can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// `-f gff3`, read by [`InterproGffReader`].
+    Gff3,
+    /// `-f tsv`, read by [`InterproTsvReader`].
+    Tsv,
+    /// `-f xml`, read by [`InterproXmlReader`].
+    Xml,
+    /// `-f json`, read by [`InterproJsonReader`].
+    Json,
+}
+
+/// Guess the format of `reader` from its first line, without consuming anything.
+///
+/// Anything that does not look like InterProScan TSV is taken for GFF3.
+pub fn detect_format<R: BufRead>(reader: &mut R) -> Result<InputFormat, Error> {
+    let buf = reader.fill_buf()?;
+    let text = String::from_utf8_lossy(buf);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let first_line = text.lines().next().unwrap_or("");
+
+    if first_line.starts_with('<') {
+        return Ok(InputFormat::Xml);
+    }
+
+    if first_line.starts_with('{') || first_line.starts_with('[') {
+        return Ok(InputFormat::Json);
+    }
+
+    if first_line.starts_with('#') {
+        return Ok(InputFormat::Gff3);
+    }
+
+    let columns: Vec<&str> = first_line.split('\t').collect();
+    if tsv::COLUMNS.contains(&columns.len()) && columns[2].parse::<u64>().is_ok() {
+        Ok(InputFormat::Tsv)
+    } else {
+        Ok(InputFormat::Gff3)
+    }
+}
+
+/// Maximum number of [`Diagnostic`]s kept by a lenient reader; later ones are only counted.
+pub const MAX_DIAGNOSTICS: usize = 100;
+
+/// How a reader reacts to a line it cannot parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Fail on the first malformed line.
+    Strict,
+    /// Skip malformed lines and record them as [`Diagnostic`]s. A corrupt gzip stream ends
+    /// the input instead of failing.
+    Lenient,
+}
+
+/// A line skipped by a lenient reader.
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// 1-based line number in the input.
+    pub line_no: usize,
+    /// Why the line was skipped.
+    pub error: Error,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.error.line_no().is_some() {
+            write!(f, "{}", self.error)
+        } else {
+            write!(f, "line {}: {}", self.line_no, self.error)
+        }
+    }
+}
+
+/// The proteins of a reader, whatever the input format.
+pub trait Records: Iterator<Item = Result<GeneRecord, Error>> {
+    /// The first [`MAX_DIAGNOSTICS`] lines skipped so far in lenient mode.
+    fn diagnostics(&self) -> &[Diagnostic];
+
+    /// Number of lines skipped so far in lenient mode.
+    fn skipped_lines(&self) -> usize;
+}
+
+/// Line-by-line input of the text readers: keeps track of where each line starts and
+/// skips the ones that cannot be read in lenient mode.
+struct LineReader<R> {
+    reader: R,
+    line: String,
+    line_no: usize,
+    offset: u64,
+    strictness: Strictness,
+    diagnostics: Vec<Diagnostic>,
+    skipped_lines: usize,
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(reader: R, strictness: Strictness) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_no: 0,
+            offset: 0,
+            strictness,
+            diagnostics: Vec::new(),
+            skipped_lines: 0,
+        }
+    }
+
+    /// The next line without its line ending, with its line number and byte offset.
+    /// `None` at the end of input.
+    fn next_line(&mut self) -> Result<Option<(&str, usize, u64)>, Error> {
+        loop {
+            self.offset += self.line.len() as u64;
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(err) => {
+                    self.line_no += 1;
+                    let err = Error::from(err);
+                    // nothing after a corrupt compressed block can be decoded
+                    let is_fatal = matches!(err, Error::Gzip(_) | Error::Decompress(..));
+                    self.tolerate(err)?;
+
+                    if is_fatal {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        self.line_no += 1;
+
+        let line = self.line.trim_end_matches(&['\n', '\r'][..]);
+        Ok(Some((line, self.line_no, self.offset)))
+    }
+
+    /// Record `err` as a diagnostic in lenient mode, or give it back when it cannot be
+    /// skipped.
+    fn tolerate(&mut self, err: Error) -> Result<(), Error> {
+        let is_recoverable = match &err {
+            Error::Io(err) => err.kind() == io::ErrorKind::InvalidData,
+            Error::Gzip(_)
+            | Error::Decompress(..)
+            | Error::MalformedLine { .. }
+            | Error::InvalidCoordinate { .. }
+            | Error::InvalidScore { .. }
+            | Error::InvalidSequence { .. }
+            | Error::UngroupedMatch { .. } => true,
+            Error::Xml { .. }
+            | Error::Json { .. }
+            | Error::Expr { .. }
+            | Error::UnknownSource { .. }
+            | Error::DuplicateId { .. } => false,
+        };
+
+        if self.strictness == Strictness::Strict || !is_recoverable {
+            return Err(err);
+        }
+
+        let line_no = err.line_no().unwrap_or(self.line_no);
+        debug!("skipped line {}: {}", line_no, err);
+        self.skipped_lines += 1;
+        if self.diagnostics.len() < MAX_DIAGNOSTICS {
+            self.diagnostics.push(Diagnostic {
+                line_no,
+                error: err,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Proteins held until the end of the input, in the order they were first seen.
+#[derive(Default)]
+struct Buffered {
+    records: Vec<GeneRecord>,
+    index: HashMap<String, usize>,
+}
+
+impl Buffered {
+    /// Add `gene_record` unless a protein with the same id is already held.
+    fn insert(&mut self, gene_record: GeneRecord) {
+        if !self.index.contains_key(&gene_record.id) {
+            self.index
+                .insert(gene_record.id.clone(), self.records.len());
+            self.records.push(gene_record);
+        }
+    }
+
+    fn get_mut(&mut self, id: &str) -> Option<&mut GeneRecord> {
+        let index = *self.index.get(id)?;
+        self.records.get_mut(index)
+    }
+
+    fn drain(&mut self) -> std::vec::IntoIter<GeneRecord> {
+        self.index.clear();
+        std::mem::take(&mut self.records).into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test_detect_format {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        assert_eq!(
+            detect_format(&mut read_with_gz(&path).unwrap()).unwrap(),
+            InputFormat::Gff3
+        );
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.tsv");
+        let mut input = read_with_gz(&path).unwrap();
+        assert_eq!(detect_format(&mut input).unwrap(), InputFormat::Tsv);
+        // nothing was consumed
+        assert_eq!(InterproTsvReader::new(input).finish().unwrap().len(), 75);
+
+        let mut input = "##gff-version 3\n".as_bytes();
+        assert_eq!(detect_format(&mut input).unwrap(), InputFormat::Gff3);
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.xml");
+        assert_eq!(
+            detect_format(&mut read_with_gz(&path).unwrap()).unwrap(),
+            InputFormat::Xml
+        );
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.json");
+        assert_eq!(
+            detect_format(&mut read_with_gz(&path).unwrap()).unwrap(),
+            InputFormat::Json
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use std::{collections::HashSet, io::BufRead, ops::RangeInclusive};
+
+use super::{Buffered, Diagnostic, LineReader, Records, Strictness};
+use crate::attributes::Attributes;
+use crate::error::Error;
+use crate::filter::{filter_methods, RecordFilter};
+use crate::records::{DomainRecord, GeneRecord};
+
+/// InterProScan writes 11 columns, 13 with InterPro entries, and up to 15 with `--goterms`
+/// and `--pathways`.
+pub(super) const COLUMNS: RangeInclusive<usize> = 11..=15;
+
+/// A missing value: InterProScan writes `-`, some versions leave the column empty.
+fn value(column: &str) -> Option<&str> {
+    match column.trim() {
+        "" | "-" => None,
+        value => Some(value),
+    }
+}
+
+/// Parse one line of InterProScan TSV into the protein it belongs to and the match it
+/// describes.
+///
+/// Every line repeats the protein, so the [`GeneRecord`] carries no domains. The optional
+/// columns end up in the attributes GFF3 would have: the InterPro accession and pathways
+/// in `Dbxref`, GO terms in `Ontology_term` and the InterPro description in
+/// `interpro_desc`. `line_no` and `offset` are only used for error reporting.
+pub fn parse_tsv_line(
+    line: &str,
+    line_no: usize,
+    offset: u64,
+) -> Result<(GeneRecord, DomainRecord), Error> {
+    let records: Vec<&str> = line.trim_end().split('\t').collect();
+    if !COLUMNS.contains(&records.len()) {
+        return Err(Error::MalformedLine {
+            line_no,
+            offset,
+            expected: "11 to 15",
+            columns: records.len(),
+        });
+    }
+
+    let coordinate = |column: usize| {
+        records[column - 1]
+            .parse::<u64>()
+            .map_err(|_| Error::InvalidCoordinate {
+                line_no,
+                offset,
+                column,
+                value: records[column - 1].to_string(),
+            })
+    };
+    let column = |column: usize| records.get(column - 1).and_then(|x| value(x));
+
+    let id = records[0];
+    let length = coordinate(3)?;
+    let source = records[3];
+    let start = coordinate(7)?;
+    let end = coordinate(8)?;
+    let score = match value(records[8]) {
+        None => None,
+        Some(value) => Some(value.parse::<f64>().map_err(|_| Error::InvalidScore {
+            line_no,
+            offset,
+            column: 9,
+            value: value.to_string(),
+        })?),
+    };
+
+    let mut gene_attributes = Attributes::default();
+    gene_attributes.push("ID", id);
+    if let Some(md5) = column(2) {
+        gene_attributes.push("md5", md5);
+    }
+
+    let mut attributes = Attributes::default();
+    if let Some(date) = column(11) {
+        attributes.push("date", date);
+    }
+    attributes.push("Target", format!("{} {} {}", id, start, end));
+    if let Some(name) = column(5) {
+        attributes.push("Name", name);
+    }
+    if let Some(desc) = column(6) {
+        attributes.push("signature_desc", desc);
+    }
+    if let Some(status) = column(10) {
+        attributes.push("status", status);
+    }
+    if let Some(accession) = column(12) {
+        attributes.push("Dbxref", format!("InterPro:{}", accession));
+    }
+    if let Some(desc) = column(13) {
+        attributes.push("interpro_desc", desc);
+    }
+    if let Some(terms) = column(14) {
+        // GO:0005515(InterPro)|GO:0006281(PANTHER) in recent versions
+        let terms = terms
+            .split('|')
+            .map(|x| x.split('(').next().unwrap_or_default().trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+        attributes.extend("Ontology_term", terms);
+    }
+    if let Some(pathways) = column(15) {
+        // MetaCyc: PWY-5663|Reactome: R-HSA-5693565
+        let pathways = pathways
+            .split('|')
+            .map(|x| {
+                x.split(':')
+                    .map(|x| x.trim())
+                    .collect::<Vec<&str>>()
+                    .join(":")
+            })
+            .filter(|x| !x.is_empty())
+            .collect();
+        attributes.extend("Dbxref", pathways);
+    }
+
+    let domain_name = attributes.name().unwrap_or("No Name").to_string();
+    let domain_desc = attributes
+        .signature_desc()
+        .unwrap_or("No Description")
+        .to_string();
+
+    Ok((
+        GeneRecord::new(id.to_string(), 1, length).with_attributes(gene_attributes),
+        DomainRecord::new(source, start, end, &domain_name, &domain_desc)
+            .with_attributes(attributes)
+            .with_score(score),
+    ))
+}
+
+/// Builder that reads InterProScan TSV (`-f tsv`) into the same [`GeneRecord`]s as
+/// [`InterproGffReader`](super::InterproGffReader).
+#[must_use]
+pub struct InterproTsvReader<R: BufRead> {
+    reader: R,
+    filter: RecordFilter,
+    buffering: bool,
+    strictness: Strictness,
+}
+
+impl<R: BufRead> InterproTsvReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            filter: RecordFilter::default(),
+            buffering: false,
+            strictness: Strictness::Strict,
+        }
+    }
+
+    filter_methods!(filter);
+
+    /// Hold every protein until the end of the input instead of yielding it as soon as its
+    /// lines end. Needed when the lines of a protein are not next to each other.
+    pub fn with_buffering(mut self, buffering: bool) -> Self {
+        self.buffering = buffering;
+        self
+    }
+
+    /// Choose whether malformed lines abort reading or are skipped. Defaults to
+    /// [`Strictness::Strict`].
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Stream the proteins that pass every filter, one at a time.
+    pub fn records(self) -> TsvRecords<R> {
+        TsvRecords {
+            lines: LineReader::new(self.reader, self.strictness),
+            filter: self.filter,
+            buffering: self.buffering,
+            current: None,
+            yielded: HashSet::new(),
+            buffered: Buffered::default(),
+            drain: None,
+            finished: false,
+        }
+    }
+
+    /// Read the whole input and return the proteins that pass every filter.
+    pub fn finish(self) -> Result<Vec<GeneRecord>, Error> {
+        self.with_buffering(true).records().collect()
+    }
+}
+
+/// Iterator over the proteins of an [`InterproTsvReader`], see [`InterproTsvReader::records`].
+///
+/// InterProScan writes the matches of a sequence on consecutive lines, so a protein is
+/// yielded as soon as a line for another one shows up. A line for a protein that has
+/// already been yielded raises [`Error::UngroupedMatch`], or is skipped with a diagnostic
+/// in lenient mode. Use [`InterproTsvReader::with_buffering`] for such inputs.
+///
+/// The ids of the proteins already yielded are kept to recognize these lines, so memory
+/// still grows with the number of proteins, by their ids only.
+///
+/// Sequences without any match do not appear in InterProScan TSV at all.
+pub struct TsvRecords<R: BufRead> {
+    lines: LineReader<R>,
+    filter: RecordFilter,
+    buffering: bool,
+    current: Option<GeneRecord>,
+    /// Proteins already yielded, or about to be, in streaming mode.
+    yielded: HashSet<String>,
+    buffered: Buffered,
+    drain: Option<std::vec::IntoIter<GeneRecord>>,
+    finished: bool,
+}
+
+impl<R: BufRead> TsvRecords<R> {
+    /// Read a single line and return the protein it completes, if any.
+    fn step(&mut self) -> Result<Option<GeneRecord>, Error> {
+        let (line, line_no, offset) = match self.lines.next_line()? {
+            Some(next) => next,
+            None => return Ok(self.end_of_input()),
+        };
+
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let (gene_record, domain) = match parse_tsv_line(line, line_no, offset) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.lines.tolerate(err)?;
+                return Ok(None);
+            }
+        };
+
+        if !self.filter.matches_id(&gene_record.id)?
+            || !self.filter.is_in_length_range(&gene_record)
+        {
+            return Ok(None);
+        }
+
+        let domain = Some(domain).filter(|x| self.filter.is_significant(x));
+        let id = gene_record.id.clone();
+
+        let completed = if self.buffering {
+            self.buffered.insert(gene_record);
+            None
+        } else if self.current.as_ref().is_some_and(|x| x.id == id) {
+            None
+        } else if self.yielded.contains(&id) {
+            self.lines.tolerate(Error::UngroupedMatch {
+                line_no,
+                offset,
+                id,
+            })?;
+            return Ok(None);
+        } else {
+            let completed = self.current.replace(gene_record);
+            if let Some(gene_record) = &completed {
+                self.yielded.insert(gene_record.id.clone());
+            }
+            completed
+        };
+
+        let current = if self.buffering {
+            self.buffered.get_mut(&id)
+        } else {
+            self.current.as_mut()
+        };
+        if let (Some(gene_record), Some(domain)) = (current, domain) {
+            gene_record.push_domain(domain);
+        }
+
+        Ok(completed)
+    }
+
+    fn end_of_input(&mut self) -> Option<GeneRecord> {
+        self.finished = true;
+        self.drain = Some(self.buffered.drain());
+        self.current.take()
+    }
+}
+
+impl<R: BufRead> Iterator for TsvRecords<R> {
+    type Item = Result<GeneRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let completed = if let Some(drain) = self.drain.as_mut() {
+                Some(drain.next()?)
+            } else if self.finished {
+                return None;
+            } else {
+                match self.step() {
+                    Ok(completed) => completed,
+                    Err(err) => return Some(Err(err)),
+                }
+            };
+
+            if let Some(gene_record) = completed.and_then(|x| self.filter.finalize(x)) {
+                return Some(Ok(gene_record));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Records for TsvRecords<R> {
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.lines.diagnostics
+    }
+
+    fn skipped_lines(&self) -> usize {
+        self.lines.skipped_lines
+    }
+}
+
+#[cfg(test)]
+mod test_tsv {
+    use super::*;
+    use crate::reader::{read_with_gz, InterproGffReader};
+
+    const LINE: &str = "P51587\t14086411a2cdf1c4cba63020e1622579\t3418\tPfam\tPF09103\t\
+        BRCA2, oligonucleotide/oligosaccharide-binding, domain 1\t2670\t2799\t7.9E-43\tT\t\
+        15-03-2013\tIPR015252\tBreast cancer type 2 susceptibility protein, helical domain\t\
+        GO:0003677(InterPro)|GO:0006302(InterPro)\tReactome: R-HSA-5685942|MetaCyc: PWY-7511";
+
+    fn read(lines: &[&str], buffering: bool) -> Vec<GeneRecord> {
+        let input = lines.join("\n");
+        InterproTsvReader::new(input.as_bytes())
+            .with_buffering(buffering)
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let (gene, domain) = parse_tsv_line(LINE, 1, 0).unwrap();
+        assert_eq!(gene.id, "P51587");
+        assert_eq!(gene.length, 3418);
+        assert_eq!(gene.md5(), Some("14086411a2cdf1c4cba63020e1622579"));
+
+        assert_eq!(domain.source, "Pfam");
+        assert_eq!(domain.domain_name, "PF09103");
+        assert_eq!((domain.start, domain.end), (2670, 2799));
+        assert_eq!(domain.evalue(), Some(7.9E-43));
+        assert_eq!(domain.attributes.status(), Some("T"));
+        assert_eq!(domain.attributes.date(), Some("15-03-2013"));
+        assert_eq!(domain.interpro_accessions(), vec!["IPR015252"]);
+        assert_eq!(
+            domain.attributes.interpro_desc(),
+            Some("Breast cancer type 2 susceptibility protein, helical domain")
+        );
+        assert_eq!(domain.go_terms(), vec!["GO:0003677", "GO:0006302"]);
+        assert_eq!(
+            domain.pathways(),
+            vec!["Reactome:R-HSA-5685942", "MetaCyc:PWY-7511"]
+        );
+    }
+
+    #[test]
+    fn optional_columns() {
+        let line = "a\t-\t100\tMobiDBLite\tmobidb-lite\tconsensus disorder prediction\t\
+                    1\t20\t-\tT\t15-03-2013";
+        let (gene, domain) = parse_tsv_line(line, 1, 0).unwrap();
+        assert_eq!(gene.md5(), None);
+        assert_eq!(domain.score, None);
+        assert!(domain.dbxref().is_empty());
+
+        let err = parse_tsv_line("a\t-\t100\tPfam", 3, 12).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MalformedLine {
+                line_no: 3,
+                columns: 4,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn same_as_gff() {
+        let tsv = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.tsv");
+        let gff = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.gff3");
+        let from_tsv: Vec<GeneRecord> = InterproTsvReader::new(read_with_gz(&tsv).unwrap())
+            .records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let from_gff = InterproGffReader::new(read_with_gz(&gff).unwrap())
+            .finish()
+            .unwrap();
+
+        assert_eq!(from_tsv.len(), from_gff.len());
+        for (tsv, gff) in from_tsv.iter().zip(from_gff.iter()) {
+            assert_eq!(tsv.id, gff.id);
+            assert_eq!(tsv.length, gff.length);
+            assert_eq!(tsv.md5(), gff.md5());
+            assert_eq!(tsv.to_tsv_line(), gff.to_tsv_line());
+            for (x, y) in tsv.iter_domains().zip(gff.iter_domains()) {
+                assert_eq!(x.score, y.score);
+                assert_eq!(x.attributes.target(), y.attributes.target());
+            }
+        }
+    }
+
+    #[test]
+    fn split_protein() {
+        let a = "a\t-\t100\tPfam\tPF00001\tdesc\t10\t50\t1.0E-10\tT\t15-03-2013";
+        let b = "b\t-\t200\tPfam\tPF00002\tdesc\t20\t60\t1.0E-10\tT\t15-03-2013";
+        let lines = [a, b, &a.replace("PF00001", "PF00003")];
+
+        let input = lines.join("\n");
+        let err = InterproTsvReader::new(input.as_bytes())
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UngroupedMatch { line_no: 3, ref id, .. } if id == "a"
+        ));
+
+        let mut records = InterproTsvReader::new(input.as_bytes())
+            .with_strictness(Strictness::Lenient)
+            .records();
+        let streamed: Vec<GeneRecord> = records.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(records.skipped_lines(), 1);
+
+        let buffered = read(&lines, true);
+        assert_eq!(buffered.len(), 2);
+        let names: Vec<&str> = buffered[0]
+            .iter_domains()
+            .map(|x| x.domain_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["PF00001", "PF00003"]);
+    }
+}
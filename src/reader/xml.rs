@@ -0,0 +1,408 @@
+use std::{collections::VecDeque, io::BufRead, sync::Arc};
+
+use quick_xml::events::{BytesStart, Event};
+
+use super::matches::{Entry, Location, Match, Protein};
+use super::{Diagnostic, Records};
+use crate::attributes::Attributes;
+use crate::error::Error;
+use crate::filter::{filter_methods, RecordFilter};
+use crate::records::GeneRecord;
+
+/// Attributes of a `*-location` element kept on the domain, and their names there.
+const LOCATION_ATTRIBUTES: [(&str, &str); 6] = [
+    ("hmm-start", "hmm_start"),
+    ("hmm-end", "hmm_end"),
+    ("hmm-length", "hmm_length"),
+    ("hmm-bounds", "hmm_bounds"),
+    ("env-start", "env_start"),
+    ("env-end", "env_end"),
+];
+
+/// Builder that reads InterProScan XML (`-f xml`) into the same [`GeneRecord`]s as
+/// [`InterproGffReader`](super::InterproGffReader).
+///
+/// The XML is read one `<protein>` at a time, never as a whole document. Location details
+/// that GFF3 lacks become attributes of the domains, see [`Attributes::hmm_range`],
+/// [`Attributes::envelope`], [`Attributes::alignment`] and [`Attributes::interpro_type`].
+#[must_use]
+pub struct InterproXmlReader<R: BufRead> {
+    reader: R,
+    filter: RecordFilter,
+}
+
+impl<R: BufRead> InterproXmlReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            filter: RecordFilter::default(),
+        }
+    }
+
+    filter_methods!(filter);
+
+    /// Stream the proteins that pass every filter, one at a time.
+    pub fn records(self) -> XmlRecords<R> {
+        let mut reader = quick_xml::Reader::from_reader(self.reader);
+        reader.config_mut().trim_text(true);
+
+        XmlRecords {
+            reader,
+            buf: Vec::new(),
+            filter: self.filter,
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Read the whole input and return the proteins that pass every filter.
+    pub fn finish(self) -> Result<Vec<GeneRecord>, Error> {
+        self.records().collect()
+    }
+}
+
+/// Iterator over the proteins of an [`InterproXmlReader`], see [`InterproXmlReader::records`].
+///
+/// InterProScan writes identical sequences once, with one `<xref>` per id; each id comes
+/// out as its own [`GeneRecord`].
+pub struct XmlRecords<R: BufRead> {
+    reader: quick_xml::Reader<R>,
+    buf: Vec<u8>,
+    filter: RecordFilter,
+    pending: VecDeque<GeneRecord>,
+    finished: bool,
+}
+
+/// Which text content is being collected.
+enum Text {
+    Sequence,
+    Alignment,
+}
+
+impl<R: BufRead> XmlRecords<R> {
+    /// Read up to the end of the next `<protein>`, `None` when there is none left.
+    fn next_protein(&mut self) -> Result<Option<Protein>, Error> {
+        let mut protein: Option<Protein> = None;
+        let mut current: Option<Match> = None;
+        let mut location: Option<Location> = None;
+        let mut text: Option<Text> = None;
+
+        loop {
+            self.buf.clear();
+            let offset = self.reader.buffer_position();
+            let event = self
+                .reader
+                .read_event_into(&mut self.buf)
+                .map_err(|err| xml_error(err, offset))?;
+
+            let (element, is_empty) = match event {
+                Event::Start(element) => (element, false),
+                Event::Empty(element) => (element, true),
+                Event::End(element) => {
+                    let name = element.local_name();
+                    let name = name.as_ref();
+                    if name == b"protein" {
+                        return Ok(protein);
+                    } else if name.ends_with(b"-match") {
+                        if let (Some(protein), Some(current)) = (protein.as_mut(), current.take()) {
+                            protein.domains.extend(current.into_domains());
+                        }
+                    } else if name.ends_with(b"-location") {
+                        if let (Some(current), Some(location)) = (current.as_mut(), location.take())
+                        {
+                            current.locations.push(location);
+                        }
+                    } else if name == b"sequence" || name == b"alignment" {
+                        text = None;
+                    }
+                    continue;
+                }
+                Event::Text(content) => {
+                    let content = content.unescape().map_err(|err| xml_error(err, offset))?;
+                    match (&text, protein.as_mut(), location.as_mut()) {
+                        (Some(Text::Sequence), Some(protein), _) => {
+                            protein.sequence.push_str(content.trim())
+                        }
+                        (Some(Text::Alignment), _, Some(location)) => {
+                            location.attributes.push("alignment", content.trim())
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                Event::Eof => {
+                    return match protein {
+                        Some(_) => Err(Error::Xml {
+                            offset,
+                            message: "unexpected end of input inside <protein>".to_string(),
+                        }),
+                        None => Ok(None),
+                    };
+                }
+                _ => continue,
+            };
+
+            let name = element.local_name();
+            let name = name.as_ref();
+            if name == b"protein" {
+                protein = Some(Protein::default());
+                continue;
+            }
+
+            let protein = match protein.as_mut() {
+                Some(protein) => protein,
+                None => continue,
+            };
+            let attributes = attributes(&element, offset)?;
+            let get = |key: &str| {
+                attributes
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.clone())
+            };
+
+            match (name, current.as_mut()) {
+                (b"sequence", _) => {
+                    protein.md5 = get("md5");
+                    if !is_empty {
+                        text = Some(Text::Sequence);
+                    }
+                }
+                (b"xref", None) => protein.ids.extend(get("id")),
+                (name, None) if name.ends_with(b"-match") => {
+                    current = Some(Match {
+                        evalue: number(get("evalue"), "evalue", offset)?,
+                        score: number(get("score"), "score", offset)?,
+                        ..Match::default()
+                    });
+                }
+                (b"signature", Some(current)) => {
+                    current.accession = get("ac").unwrap_or_default();
+                    current.name = get("name");
+                    current.desc = get("desc");
+                }
+                (b"entry", Some(current)) => {
+                    current.entry = get("ac").map(|accession| Entry {
+                        accession,
+                        desc: get("desc"),
+                        entry_type: get("type"),
+                    });
+                }
+                (b"go-xref", Some(current)) => current.go_terms.extend(get("id")),
+                (b"pathway-xref", Some(current)) => {
+                    if let (Some(db), Some(id)) = (get("db"), get("id")) {
+                        current.pathways.push(format!("{}:{}", db, id));
+                    }
+                }
+                (b"signature-library-release", Some(current)) => {
+                    current.library = get("library").unwrap_or_default();
+                }
+                (name, Some(current)) if name.ends_with(b"-location") => {
+                    let coordinate = |key: &str| {
+                        number(get(key), key, offset)?.ok_or_else(|| Error::Xml {
+                            offset,
+                            message: format!("location without {}", key),
+                        })
+                    };
+
+                    let mut location_attributes = Attributes::default();
+                    for (key, renamed) in LOCATION_ATTRIBUTES.iter() {
+                        if let Some(value) = get(key) {
+                            location_attributes.push(renamed, value);
+                        }
+                    }
+                    let parsed = Location {
+                        start: coordinate("start")?,
+                        end: coordinate("end")?,
+                        evalue: number(get("evalue"), "evalue", offset)?,
+                        score: number(get("score"), "score", offset)?,
+                        attributes: location_attributes,
+                    };
+
+                    if is_empty {
+                        current.locations.push(parsed);
+                    } else {
+                        location = Some(parsed);
+                    }
+                }
+                (b"alignment", Some(_)) if !is_empty => text = Some(Text::Alignment),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The attributes of `element` by local name.
+fn attributes(element: &BytesStart, offset: u64) -> Result<Vec<(String, String)>, Error> {
+    element
+        .attributes()
+        .map(|attribute| {
+            let attribute = attribute.map_err(|err| xml_error(err, offset))?;
+            let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+            let value = attribute
+                .unescape_value()
+                .map_err(|err| xml_error(err, offset))?;
+
+            Ok((key, value.into_owned()))
+        })
+        .collect()
+}
+
+fn number<T: std::str::FromStr>(
+    value: Option<String>,
+    key: &str,
+    offset: u64,
+) -> Result<Option<T>, Error> {
+    match value {
+        None => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(|_| Error::Xml {
+            offset,
+            message: format!("invalid {}: {:?}", key, value),
+        }),
+    }
+}
+
+/// Convert a quick-xml error, keeping the [`Error`] decoders wrap into I/O errors.
+fn xml_error<E: Into<quick_xml::Error>>(err: E, offset: u64) -> Error {
+    match err.into() {
+        quick_xml::Error::Io(err) => match Arc::try_unwrap(err) {
+            Ok(err) => Error::from(err),
+            Err(err) => Error::Xml {
+                offset,
+                message: err.to_string(),
+            },
+        },
+        err => Error::Xml {
+            offset,
+            message: err.to_string(),
+        },
+    }
+}
+
+impl<R: BufRead> Iterator for XmlRecords<R> {
+    type Item = Result<GeneRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(gene_record) = self.pending.pop_front() {
+                match self.filter.apply(gene_record) {
+                    Ok(Some(gene_record)) => return Some(Ok(gene_record)),
+                    Ok(None) => continue,
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            let offset = self.reader.buffer_position();
+            let records = match self.next_protein() {
+                Ok(Some(protein)) => protein
+                    .into_records()
+                    .map_err(|message| Error::Xml { offset, message }),
+                Ok(None) => {
+                    self.finished = true;
+                    continue;
+                }
+                Err(err) => Err(err),
+            };
+
+            match records {
+                Ok(records) => self.pending.extend(records),
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Records for XmlRecords<R> {
+    /// XML cannot be resynchronised after an error, so nothing is ever skipped.
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &[]
+    }
+
+    fn skipped_lines(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test_xml {
+    use super::*;
+    use crate::parser::Expr;
+    use crate::reader::read_with_gz;
+    use crate::records::DomainRecord;
+
+    fn read() -> InterproXmlReader<Box<dyn BufRead>> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/small.xml");
+        InterproXmlReader::new(read_with_gz(&path).unwrap())
+    }
+
+    #[test]
+    fn proteins() {
+        let records = read().finish().unwrap();
+        let ids: Vec<&str> = records.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["kfl00063_0090_v1.1", "alias_1", "alias_2"]);
+
+        assert_eq!(records[0].length, 120);
+        assert_eq!(records[0].md5(), Some("1c8e1d6da147e4b7899eb2849f9781d6"));
+        assert_eq!(records[0].sequence.as_ref().map(|x| x.len()), Some(120));
+        assert_eq!(records[1].iter_domains().count(), 1);
+        assert_eq!(records[2].sequence, records[1].sequence);
+    }
+
+    #[test]
+    fn matches() {
+        let records = read().finish().unwrap();
+        let domains: Vec<&DomainRecord> = records[0].iter_domains().collect();
+        assert_eq!(domains.len(), 3);
+
+        let pfam = domains[0];
+        assert_eq!(pfam.source, "Pfam");
+        assert_eq!(pfam.domain_name, "PF00533");
+        assert_eq!((pfam.start, pfam.end), (12, 88));
+        assert_eq!(pfam.evalue(), Some(3.9E-17));
+        assert_eq!(pfam.interpro_accessions(), vec!["IPR001357"]);
+        assert_eq!(pfam.go_terms(), vec!["GO:0006281"]);
+        assert_eq!(pfam.pathways(), vec!["Reactome:R-HSA-5693565"]);
+        assert_eq!(pfam.attributes.interpro_type(), Some("DOMAIN"));
+        assert_eq!(pfam.attributes.hmm_range(), Some((2, 78)));
+        assert_eq!(pfam.attributes.hmm_bounds(), Some("INCOMPLETE"));
+        assert_eq!(pfam.attributes.envelope(), Some((11, 89)));
+        assert_eq!(domains[1].start, 95);
+
+        let profile = domains[2];
+        assert_eq!(profile.source, "ProSiteProfiles");
+        assert_eq!(profile.profile_score(), Some(14.313));
+        assert_eq!(profile.attributes.alignment(), Some("LSGKTFVITGEL"));
+    }
+
+    #[test]
+    fn filters() {
+        let records = read()
+            .with_domain_expr(Some(Expr::from_string("PS50172").unwrap()))
+            .with_max_evalue(Some(1e-10))
+            .finish()
+            .unwrap();
+
+        assert_eq!(records.len(), 1);
+        let names: Vec<&str> = records[0]
+            .iter_domains()
+            .map(|x| x.domain_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["PF00533", "PS50172"]);
+    }
+
+    #[test]
+    fn truncated() {
+        let input = "<protein-matches><protein><sequence md5=\"x\">MSTN</sequence>";
+        let err = InterproXmlReader::new(input.as_bytes())
+            .finish()
+            .unwrap_err();
+        assert!(matches!(err, Error::Xml { .. }));
+    }
+}
@@ -1,18 +1,36 @@
+use crate::attributes::Attributes;
 use crate::parser::Expr;
-use std::fmt::Display;
+use crate::utils::{score_kind, ScoreKind};
+use serde_json::{json, Value};
+use std::{collections::HashSet, fmt::Display};
 
+/// Databases InterProScan reports pathway cross-references from (`--pathways`).
+pub const PATHWAY_DATABASES: [&str; 3] = ["Reactome", "MetaCyc", "KEGG"];
+
+fn dedup<'a, I: Iterator<Item = &'a str>>(iter: I) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    iter.filter(|x| seen.insert(*x)).collect()
+}
+
+/// A single `protein_match` line: one hit of a member-database signature on a protein.
 #[derive(Debug, Clone)]
 pub struct DomainRecord {
     pub source: String,
     pub start: u64,
     pub end: u64,
+    /// Column 6: an E-value or a profile score depending on the source, see
+    /// [`DomainRecord::evalue`] and [`DomainRecord::profile_score`].
+    pub score: Option<f64>,
     pub domain_name: String,
     pub domain_desc: String,
+    /// Every attribute of the line, including `Name` and `signature_desc`.
+    pub attributes: Attributes,
 }
 
-impl ToString for DomainRecord {
-    fn to_string(&self) -> String {
-        format!(
+impl Display for DomainRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
             "{}-{} {} {}",
             self.start, self.end, self.domain_name, self.domain_desc
         )
@@ -31,20 +49,142 @@ impl DomainRecord {
             source: source.to_string(),
             start,
             end,
+            score: None,
             domain_name: domain_name.to_string(),
             domain_desc: domain_desc.to_string(),
+            attributes: Attributes::default(),
+        }
+    }
+
+    pub fn with_attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_score(mut self, score: Option<f64>) -> Self {
+        self.score = score;
+        self
+    }
+
+    /// The score if the source reports E-values (Pfam, CDD, SUPERFAMILY, Gene3D, ...).
+    pub fn evalue(&self) -> Option<f64> {
+        match score_kind(&self.source) {
+            Some(ScoreKind::EValue) => self.score,
+            _ => None,
         }
     }
 
+    /// The score if the source reports profile scores (ProSiteProfiles, HAMAP).
+    pub fn profile_score(&self) -> Option<f64> {
+        match score_kind(&self.source) {
+            Some(ScoreKind::Score) => self.score,
+            _ => None,
+        }
+    }
+
+    /// Cross-references of the match, such as `InterPro:IPR001357`.
+    pub fn dbxref(&self) -> &[String] {
+        self.attributes.dbxref()
+    }
+
+    /// Accessions of the InterPro entries the signature is integrated into (`IPR001357`).
+    pub fn interpro_accessions(&self) -> Vec<&str> {
+        self.dbxref()
+            .iter()
+            .filter_map(|x| x.strip_prefix("InterPro:"))
+            .collect()
+    }
+
+    /// GO terms (`GO:0005515`) from `Ontology_term` and `Dbxref`.
+    pub fn go_terms(&self) -> Vec<&str> {
+        self.attributes
+            .ontology_term()
+            .iter()
+            .chain(self.dbxref().iter())
+            .map(|x| x.as_str())
+            .filter(|x| x.starts_with("GO:"))
+            .collect()
+    }
+
+    /// Pathway cross-references such as `Reactome:R-HSA-5693565` or `MetaCyc:PWY-5663`.
+    pub fn pathways(&self) -> Vec<&str> {
+        self.dbxref()
+            .iter()
+            .map(|x| x.as_str())
+            .filter(|x| {
+                PATHWAY_DATABASES
+                    .iter()
+                    .any(|db| x.strip_prefix(db).is_some_and(|x| x.starts_with(':')))
+            })
+            .collect()
+    }
+
+    /// The match as a JSON object, with its InterPro entries, GO terms and pathways.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "source": self.source,
+            "name": self.domain_name,
+            "desc": self.domain_desc,
+            "start": self.start,
+            "end": self.end,
+            "score": self.score,
+            "interpro": self.interpro_accessions(),
+            "go_terms": self.go_terms(),
+            "pathways": self.pathways(),
+        })
+    }
+
+    /// The `protein_match` line of the match on protein `id`.
+    pub fn to_gff_line(&self, id: &str) -> String {
+        let score = match self.score {
+            Some(score) => format_score(score),
+            None => ".".to_string(),
+        };
+
+        // the name and description are read back from these
+        let mut attributes = self.attributes.clone();
+        if attributes.name().is_none() {
+            attributes.push("Name", &self.domain_name);
+        }
+        if attributes.signature_desc().is_none() && self.domain_desc != "No Description" {
+            attributes.push("signature_desc", &self.domain_desc);
+        }
+
+        format!(
+            "{}\t{}\tprotein_match\t{}\t{}\t{}\t+\t.\t{}",
+            id,
+            self.source,
+            self.start,
+            self.end,
+            score,
+            attributes.to_gff_column()
+        )
+    }
+
+    fn interpro_cell(&self) -> String {
+        let accessions = self.interpro_accessions();
+        if accessions.is_empty() {
+            ".".to_string()
+        } else {
+            accessions.join(",")
+        }
+    }
+
+    /// Whether this record is the `polypeptide` line of a protein rather than a match.
     pub fn is_gene(&self) -> bool {
         self.source == "."
     }
 }
 
+/// A protein (`polypeptide`) together with the domains matched on it.
 #[derive(Debug, Clone)]
 pub struct GeneRecord {
     pub id: String,
     pub length: u64,
+    /// Attributes of the polypeptide line (`ID`, `md5`).
+    pub attributes: Attributes,
+    /// The protein sequence, when the input has it.
+    pub sequence: Option<String>,
     domains: Vec<DomainRecord>,
 }
 
@@ -53,10 +193,37 @@ impl GeneRecord {
         Self {
             id,
             length: end - start + 1,
+            attributes: Attributes::default(),
+            sequence: None,
             domains: Vec::new(),
         }
     }
 
+    pub fn with_attributes(mut self, attributes: Attributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: Option<String>) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Deduplicated GO terms of all domains, in order of first appearance.
+    pub fn go_terms(&self) -> Vec<&str> {
+        dedup(self.domains.iter().flat_map(|x| x.go_terms()))
+    }
+
+    /// Deduplicated pathway cross-references of all domains, in order of first appearance.
+    pub fn pathways(&self) -> Vec<&str> {
+        dedup(self.domains.iter().flat_map(|x| x.pathways()))
+    }
+
+    /// MD5 checksum of the protein sequence reported by InterProScan.
+    pub fn md5(&self) -> Option<&str> {
+        self.attributes.md5()
+    }
+
     pub fn push_domain(&mut self, domain: DomainRecord) {
         self.domains.push(domain);
     }
@@ -65,44 +232,77 @@ impl GeneRecord {
         self.domains.iter()
     }
 
-    pub fn filter_by_source_expr(self, source_expr: &Option<Expr>) -> Self {
+    /// Keep only the domains for which `f` returns `true`.
+    pub fn retain_domains<F: FnMut(&DomainRecord) -> bool>(&mut self, f: F) {
+        self.domains.retain(f);
+    }
+
+    /// Keep only the domains whose source matches `source_expr`.
+    pub fn filter_by_source_expr(mut self, source_expr: &Option<Expr>) -> Self {
         if let Some(expr) = source_expr {
-            let domains: Vec<DomainRecord> = self
-                .iter_domains()
-                .filter(|domain| expr.matches(&[&domain.source]).expect("must ok"))
-                .cloned()
-                .collect();
-
-            Self {
-                id: self.id,
-                length: self.length,
-                domains,
-            }
-        } else {
-            self
+            self.domains
+                .retain(|domain| expr.matches(&[&domain.source]).expect("must ok"));
         }
+
+        self
     }
 
+    /// One tab-separated line for the protein followed by one per domain.
     pub fn to_tsv_line(&self) -> String {
-        // gene_id source term_id term_desc start end
+        // gene_id source term_id term_desc start end interpro
         let mut lines = Vec::with_capacity(self.domains.len() + 1);
-        lines.push(format!("{}\t.\t.\t.\t0\t{}", self.id, self.length));
+        lines.push(format!("{}\t.\t.\t.\t0\t{}\t.", self.id, self.length));
 
         for domain in self.domains.iter() {
             lines.push(format!(
-                "{}\t{}\t{}\t{}\t{}\t{}",
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
                 self.id,
                 domain.source,
                 domain.domain_name,
                 domain.domain_desc,
                 domain.start,
                 domain.end,
+                domain.interpro_cell(),
             ));
         }
 
         lines.join("\n")
     }
 
+    /// The `##sequence-region` pragma, polypeptide line and match lines of the protein, as
+    /// InterProScan writes them.
+    pub fn to_gff_lines(&self) -> Vec<String> {
+        let mut attributes = self.attributes.clone();
+        if attributes.id().is_none() {
+            attributes.push("ID", &self.id);
+        }
+
+        let mut lines = Vec::with_capacity(self.domains.len() + 2);
+        lines.push(format!("##sequence-region {} 1 {}", self.id, self.length));
+        lines.push(format!(
+            "{}\t.\tpolypeptide\t1\t{}\t.\t+\t.\t{}",
+            self.id,
+            self.length,
+            attributes.to_gff_column()
+        ));
+        for domain in self.domains.iter() {
+            lines.push(domain.to_gff_line(&self.id));
+        }
+
+        lines
+    }
+
+    /// The protein as a JSON object with its domains nested inside.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "length": self.length,
+            "md5": self.md5(),
+            "sequence": self.sequence,
+            "domains": self.domains.iter().map(|x| x.to_json()).collect::<Vec<Value>>(),
+        })
+    }
+
     pub fn to_table_row(&self) -> Vec<Vec<String>> {
         let mut cells = Vec::with_capacity(self.domains.len() + 1);
 
@@ -113,6 +313,7 @@ impl GeneRecord {
             ".".to_string(),
             "0".to_string(),
             self.length.to_string(),
+            ".".to_string(),
         ]);
 
         for domain in self.domains.iter() {
@@ -123,6 +324,7 @@ impl GeneRecord {
                 domain.domain_desc.to_owned(),
                 domain.start.to_string(),
                 domain.end.to_string(),
+                domain.interpro_cell(),
             ])
         }
 
@@ -130,6 +332,36 @@ impl GeneRecord {
     }
 }
 
+/// Scores in the notation InterProScan uses for E-values (`5.71601E-12`) when they are
+/// very small or large, as plain decimals otherwise.
+fn format_score(score: f64) -> String {
+    if score != 0.0 && !(1e-3..1e6).contains(&score.abs()) {
+        format!("{:E}", score)
+    } else {
+        score.to_string()
+    }
+}
+
+/// Orders in which [`sort_records`] can arrange proteins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Lexicographically by protein id.
+    Id,
+    /// Shortest protein first.
+    Length,
+    /// Protein with the fewest domains first.
+    DomainCount,
+}
+
+/// Sort `records` by `key`. The sort is stable, so ties keep their input order.
+pub fn sort_records(records: &mut [GeneRecord], key: SortKey) {
+    match key {
+        SortKey::Id => records.sort_by(|a, b| a.id.cmp(&b.id)),
+        SortKey::Length => records.sort_by_key(|x| x.length),
+        SortKey::DomainCount => records.sort_by_key(|x| x.domains.len()),
+    }
+}
+
 impl Display for GeneRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let header = format!("--- id: {}, length {} ---", self.id, self.length);
@@ -143,3 +375,119 @@ impl Display for GeneRecord {
         write!(f, "{}\n{}", header, domains)
     }
 }
+
+#[cfg(test)]
+mod test_records {
+    use super::*;
+
+    fn gene(id: &str, length: u64, n_domains: usize) -> GeneRecord {
+        let mut record = GeneRecord::new(id.to_string(), 1, length);
+        for _ in 0..n_domains {
+            record.push_domain(DomainRecord::new("Pfam", 1, 10, "PF00001", "desc"));
+        }
+        record
+    }
+
+    fn ids(records: &[GeneRecord]) -> Vec<&str> {
+        records.iter().map(|x| x.id.as_str()).collect()
+    }
+
+    #[test]
+    fn go_terms_and_pathways() {
+        let attributes = |column: &str| Attributes::parse(column);
+        let mut record = GeneRecord::new("a".to_string(), 1, 100);
+        record.push_domain(
+            DomainRecord::new("Pfam", 1, 10, "PF00001", "desc").with_attributes(attributes(
+                "Ontology_term=\"GO:0005515\",\"GO:0006281\";\
+                 Dbxref=\"InterPro:IPR001357\",\"Reactome:R-HSA-5693565\"",
+            )),
+        );
+        record.push_domain(
+            DomainRecord::new("SMART", 1, 10, "SM00292", "desc").with_attributes(attributes(
+                "Dbxref=\"GO:0005515\",\"MetaCyc:PWY-5663\",\"KEGGish:1\"",
+            )),
+        );
+
+        assert_eq!(record.go_terms(), vec!["GO:0005515", "GO:0006281"]);
+        assert_eq!(
+            record.pathways(),
+            vec!["Reactome:R-HSA-5693565", "MetaCyc:PWY-5663"]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let mut record = gene("a", 100, 0).with_attributes(Attributes::parse(
+            "ID=a;md5=4a9d57d71a755d8530879de3c58a2545",
+        ));
+        record.push_domain(
+            DomainRecord::new("Pfam", 5, 40, "PF00533", "BRCT")
+                .with_attributes(Attributes::parse(
+                    "Dbxref=\"InterPro:IPR001357\";Ontology_term=\"GO:0005515\"",
+                ))
+                .with_score(Some(1.5e-10)),
+        );
+
+        assert_eq!(
+            record.to_json(),
+            json!({
+                "id": "a",
+                "length": 100,
+                "md5": "4a9d57d71a755d8530879de3c58a2545",
+                "sequence": null,
+                "domains": [{
+                    "source": "Pfam",
+                    "name": "PF00533",
+                    "desc": "BRCT",
+                    "start": 5,
+                    "end": 40,
+                    "score": 1.5e-10,
+                    "interpro": ["IPR001357"],
+                    "go_terms": ["GO:0005515"],
+                    "pathways": [],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn gff_lines() {
+        let mut record = gene("a", 100, 0).with_attributes(Attributes::parse(
+            "ID=a;md5=4a9d57d71a755d8530879de3c58a2545",
+        ));
+        record.push_domain(
+            DomainRecord::new("CDD", 103, 170, "cd00027", "BRCT")
+                .with_attributes(Attributes::parse("Name=cd00027;signature_desc=BRCT"))
+                .with_score(Some(5.71601E-12)),
+        );
+        record.push_domain(
+            DomainRecord::new("ProSiteProfiles", 99, 184, "PS50172", "BRCT")
+                .with_score(Some(14.313)),
+        );
+
+        assert_eq!(
+            record.to_gff_lines(),
+            vec![
+                "##sequence-region a 1 100",
+                "a\t.\tpolypeptide\t1\t100\t.\t+\t.\tID=a;md5=4a9d57d71a755d8530879de3c58a2545",
+                "a\tCDD\tprotein_match\t103\t170\t5.71601E-12\t+\t.\tName=cd00027;signature_desc=BRCT",
+                "a\tProSiteProfiles\tprotein_match\t99\t184\t14.313\t+\t.\tName=PS50172;signature_desc=BRCT",
+            ]
+        );
+    }
+
+    #[test]
+    fn sort() {
+        let mut records = vec![gene("b", 30, 1), gene("c", 10, 2), gene("a", 20, 1)];
+
+        sort_records(&mut records, SortKey::Id);
+        assert_eq!(ids(&records), vec!["a", "b", "c"]);
+
+        sort_records(&mut records, SortKey::Length);
+        assert_eq!(ids(&records), vec!["c", "a", "b"]);
+
+        // stable: "a" stays before "b"
+        sort_records(&mut records, SortKey::DomainCount);
+        assert_eq!(ids(&records), vec!["a", "b", "c"]);
+    }
+}
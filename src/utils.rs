@@ -1,8 +1,8 @@
-use std::error::Error;
+use crate::error::Error;
+use crate::parser::Expr;
 
-use crate::parser::{ast::ParseError, Expr};
-
-pub const SOURCE_NAMES: [&str; 15] = [
+/// Source names of the GFF3 and TSV output, one for each library of the XML and JSON output.
+pub const SOURCE_NAMES: [&str; 20] = [
     "MobiDBLite",
     "Gene3D",
     "ProSitePatterns",
@@ -18,19 +18,124 @@ pub const SOURCE_NAMES: [&str; 15] = [
     "Coils",
     "PIRSR",
     "SFLD",
+    "HAMAP",
+    "NCBIfam",
+    "AntiFam",
+    "FunFam",
+    "SignalP",
+];
+
+/// Library names of the XML and JSON output and the matching source names of GFF3 and TSV.
+const LIBRARY_NAMES: [(&str, &str); 20] = [
+    ("MOBIDB_LITE", "MobiDBLite"),
+    ("GENE3D", "Gene3D"),
+    ("PROSITE_PATTERNS", "ProSitePatterns"),
+    ("PANTHER", "PANTHER"),
+    ("CDD", "CDD"),
+    ("PFAM", "Pfam"),
+    ("SUPERFAMILY", "SUPERFAMILY"),
+    ("PROSITE_PROFILES", "ProSiteProfiles"),
+    ("PRINTS", "PRINTS"),
+    ("PIRSF", "PIRSF"),
+    ("TIGRFAM", "TIGRFAM"),
+    ("SMART", "SMART"),
+    ("COILS", "Coils"),
+    ("PIRSR", "PIRSR"),
+    ("SFLD", "SFLD"),
+    ("HAMAP", "HAMAP"),
+    ("NCBIFAM", "NCBIfam"),
+    ("ANTIFAM", "AntiFam"),
+    ("FUNFAM", "FunFam"),
+    ("SIGNALP", "SignalP"),
 ];
 
-pub fn validate_source_expr(source_expr: &Option<Expr>) -> Result<(), Box<dyn Error>> {
+/// The source name GFF3 and TSV use for a library of the XML and JSON output, such as
+/// `Pfam` for `PFAM`. Unknown libraries are kept as they are.
+pub fn source_name(library: &str) -> String {
+    LIBRARY_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(library))
+        .map_or(library, |(_, source)| source)
+        .to_string()
+}
+
+/// What the score column (column 6) holds for a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreKind {
+    /// An E-value: lower is better.
+    EValue,
+    /// A profile score: higher is better.
+    Score,
+}
+
+/// Sources whose score column holds an E-value.
+pub const EVALUE_SOURCES: [&str; 13] = [
+    "Pfam",
+    "CDD",
+    "SUPERFAMILY",
+    "Gene3D",
+    "PANTHER",
+    "SMART",
+    "TIGRFAM",
+    "PIRSF",
+    "PIRSR",
+    "PRINTS",
+    "SFLD",
+    "NCBIfam",
+    "FunFam",
+];
+
+/// Sources whose score column holds a profile score rather than an E-value.
+pub const SCORE_SOURCES: [&str; 2] = ["ProSiteProfiles", "HAMAP"];
+
+/// How to interpret the score of a match from `source`, `None` for the polypeptide line
+/// and for sources without scores or with other scores, such as the probabilities of
+/// SignalP.
+pub fn score_kind(source: &str) -> Option<ScoreKind> {
+    if EVALUE_SOURCES.contains(&source) {
+        Some(ScoreKind::EValue)
+    } else if SCORE_SOURCES.contains(&source) {
+        Some(ScoreKind::Score)
+    } else {
+        None
+    }
+}
+
+pub fn validate_source_expr(source_expr: &Option<Expr>) -> Result<(), Error> {
     if let Some(expr) = source_expr {
         if expr.matches(&SOURCE_NAMES)? {
             Ok(())
         } else {
-            Err(Box::new(ParseError::new(format!(
-                "Invalid source expr. Please select from [{}]",
-                SOURCE_NAMES.join(" ")
-            ))))
+            Err(Error::UnknownSource {
+                expected: SOURCE_NAMES.iter().map(|x| x.to_string()).collect(),
+            })
         }
     } else {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test_utils {
+    use super::*;
+
+    #[test]
+    fn source_names() {
+        for (_, source) in LIBRARY_NAMES {
+            assert!(SOURCE_NAMES.contains(&source), "{}", source);
+        }
+
+        let expr = Expr::from_string("HAMAP | NCBIfam").unwrap();
+        assert!(validate_source_expr(&Some(expr)).is_ok());
+    }
+
+    #[test]
+    fn score_kinds() {
+        assert_eq!(score_kind("Pfam"), Some(ScoreKind::EValue));
+        assert_eq!(score_kind("HAMAP"), Some(ScoreKind::Score));
+        assert_eq!(score_kind("SignalP"), None);
+        assert_eq!(score_kind("Phobius"), None);
+        assert_eq!(score_kind("Coils"), None);
+        assert_eq!(score_kind("."), None);
+    }
+}